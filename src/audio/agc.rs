@@ -0,0 +1,144 @@
+use crate::config::AudioConfig;
+
+/// Evens out the input level before analysis
+///
+/// Measures the RMS level of each frame and moves the gain towards whatever
+/// brings it to the target level: quickly (attack) when the input gets louder,
+/// slowly (release) when it gets quieter. Frames below the silence threshold
+/// leave the gain alone, so pauses and noise floors are not blown up. The gain
+/// is ramped across each frame to avoid steps in the waveform.
+pub struct AutomaticGainControl {
+    target_db: f32,
+    max_gain_db: f32,
+    gate_db: f32,
+    attack_ms: f32,
+    release_ms: f32,
+    /// Current gain, None until the first frame above the gate
+    gain_db: Option<f32>,
+}
+
+impl AutomaticGainControl {
+    /// Create a gain control with the `agc_*` settings of `config`
+    pub fn new(config: &AudioConfig) -> Self {
+        Self {
+            target_db: config.agc_target_db,
+            max_gain_db: config.agc_max_gain_db.max(0.0),
+            gate_db: config.silence_threshold_db,
+            attack_ms: config.agc_attack_ms.max(0.0),
+            release_ms: config.agc_release_ms.max(0.0),
+            gain_db: None,
+        }
+    }
+    
+    /// Gain currently applied (dB)
+    pub fn gain_db(&self) -> f32 {
+        self.gain_db.unwrap_or(0.0)
+    }
+    
+    /// Adapt the gain to interleaved `samples` captured at `sample_rate` and apply it in place
+    pub fn process(&mut self, samples: &mut [f32], channels: u16, sample_rate: u32) {
+        if samples.is_empty() {
+            return;
+        }
+        
+        let rms = (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt();
+        let level_db = 20.0 * rms.max(1e-10).log10();
+        let previous_db = self.gain_db();
+        
+        if level_db >= self.gate_db {
+            let wanted_db = (self.target_db - level_db).min(self.max_gain_db);
+            self.gain_db = Some(match self.gain_db {
+                // Jump straight to the right gain on the first audible frame
+                None => wanted_db,
+                Some(gain_db) => {
+                    let time_ms = if wanted_db < gain_db { self.attack_ms } else { self.release_ms };
+                    let frame_ms = samples.len() as f32 / channels.max(1) as f32 / sample_rate.max(1) as f32 * 1000.0;
+                    let coefficient = if time_ms > 0.0 { 1.0 - (-frame_ms / time_ms).exp() } else { 1.0 };
+                    gain_db + (wanted_db - gain_db) * coefficient
+                }
+            });
+        }
+        
+        let from = db_to_gain(previous_db);
+        let to = db_to_gain(self.gain_db());
+        let channels = channels.max(1) as usize;
+        let frames = samples.len() / channels;
+        for (index, frame) in samples.chunks_mut(channels).enumerate() {
+            let gain = from + (to - from) * (index + 1) as f32 / frames.max(1) as f32;
+            for sample in frame {
+                *sample *= gain;
+            }
+        }
+    }
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn sine(amplitude: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|n| (2.0 * std::f32::consts::PI * 440.0 * n as f32 / 44100.0).sin() * amplitude)
+            .collect()
+    }
+    
+    fn rms_db(samples: &[f32]) -> f32 {
+        let rms = (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt();
+        20.0 * rms.log10()
+    }
+    
+    #[test]
+    fn test_levels_are_normalized() {
+        let config = AudioConfig {
+            agc_target_db: -20.0,
+            agc_attack_ms: 50.0,
+            agc_max_gain_db: 30.0,
+            ..AudioConfig::default()
+        };
+        
+        // A quiet mic and a hot loopback end up at the same level
+        for amplitude in [0.01, 0.1, 0.9] {
+            let mut agc = AutomaticGainControl::new(&config);
+            let mut frame = sine(amplitude, 1024);
+            for _ in 0..10 {
+                frame = sine(amplitude, 1024);
+                agc.process(&mut frame, 1, 44100);
+            }
+            assert!((rms_db(&frame) + 20.0).abs() < 0.1, "amplitude {} came out at {} dB", amplitude, rms_db(&frame));
+        }
+        
+        // Getting louder brings the gain down within the attack time
+        let mut agc = AutomaticGainControl::new(&config);
+        agc.process(&mut sine(0.1, 1024), 1, 44100);
+        let quiet_gain = agc.gain_db();
+        for _ in 0..10 {
+            agc.process(&mut sine(0.5, 1024), 1, 44100);
+        }
+        assert!((quiet_gain - agc.gain_db() - 20.0 * 5f32.log10()).abs() < 0.5);
+    }
+    
+    #[test]
+    fn test_gain_is_capped_and_gated() {
+        let config = AudioConfig {
+            agc_target_db: -20.0,
+            agc_max_gain_db: 12.0,
+            silence_threshold_db: -60.0,
+            ..AudioConfig::default()
+        };
+        let mut agc = AutomaticGainControl::new(&config);
+        
+        agc.process(&mut sine(0.01, 1024), 1, 44100);
+        assert_eq!(agc.gain_db(), 12.0);
+        
+        // Silence and a -70 dB noise floor don't move the gain
+        for level in [0.0, 3e-4] {
+            let mut frame = vec![level; 1024];
+            agc.process(&mut frame, 1, 44100);
+            assert_eq!(agc.gain_db(), 12.0);
+        }
+    }
+}
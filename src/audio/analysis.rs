@@ -1,20 +1,35 @@
 use anyhow::{Context, Result};
 use num_complex::Complex;
 use realfft::{RealFftPlanner, RealToComplex};
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use super::{AudioFrame, AudioSample, AudioData};
+use super::agc::AutomaticGainControl;
+use super::beat::BeatTracker;
+use super::channels::remix;
+use super::chroma::{ChromaTracker, Harmony};
+use super::filterbank::Filterbank;
+use super::levels::{LevelTracker, MilkdropLevels};
+use super::onset::{BandOnsets, OnsetDetector};
+use super::resample::SincResampler;
+use super::resolution::ResolutionAnalyzer;
+use super::{AudioFrame, AudioSample, AudioData, AudioEvent, AudioQueueStats, ChannelData};
 use crate::config::AudioConfig;
 
 /// Frequency domain data from FFT analysis
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FrequencyData {
     /// Frequency bins (magnitude)
     pub bins: Vec<f32>,
     
     /// Frequency range for each bin (Hz)
-    pub bin_frequencies: Vec<f32>,
+    ///
+    /// The same for every frame, so it is shared rather than copied, and traces
+    /// store it once in their header.
+    #[serde(skip)]
+    pub bin_frequencies: Arc<[f32]>,
     
     /// Peak frequency (Hz)
     pub peak_frequency: f32,
@@ -27,7 +42,7 @@ pub struct FrequencyData {
 }
 
 /// Extracted audio features for visualization
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AudioFeatures {
     /// Overall volume (RMS)
     pub volume: f32,
@@ -47,9 +62,37 @@ pub struct AudioFeatures {
     /// Beat detection confidence (0.0 - 1.0)
     pub beat_confidence: f32,
     
+    /// Spectral flux onset strength, how sharply the most active band rose (0.0 - 1.0)
+    #[serde(default)]
+    pub onset_strength: f32,
+    
+    /// Bands with an onset (transient) in this frame
+    #[serde(default)]
+    pub onsets: BandOnsets,
+    
     /// Estimated tempo (BPM)
     pub tempo: f32,
     
+    /// How periodic the onsets are at that tempo (0.0 - 1.0)
+    #[serde(default)]
+    pub tempo_confidence: f32,
+    
+    /// Position within the current beat, 0.0 on the beat (0.0 - 1.0)
+    #[serde(default)]
+    pub beat_phase: f32,
+    
+    /// Position within the current 4/4 bar, 0.0 on the downbeat (0.0 - 1.0)
+    #[serde(default)]
+    pub bar_phase: f32,
+    
+    /// Bass, mid and treble relative to the song's average, as MilkDrop presets expect them
+    #[serde(default)]
+    pub milkdrop: MilkdropLevels,
+    
+    /// Chroma, dominant pitch class and estimated key
+    #[serde(default)]
+    pub harmony: Harmony,
+    
     /// Zero crossing rate (indication of pitch)
     pub zero_crossing_rate: f32,
     
@@ -60,6 +103,68 @@ pub struct AudioFeatures {
     pub spectral_rolloff: f32,
 }
 
+/// Relationship between the left and right channels
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StereoFeatures {
+    /// Side to mid ratio (0.0 mono, ~0.5 unrelated channels, 1.0 fully out of phase)
+    pub width: f32,
+    
+    /// Level balance (-1.0 left only, 0.0 centered, 1.0 right only)
+    pub balance: f32,
+    
+    /// Phase correlation (1.0 mono, 0.0 unrelated, -1.0 out of phase)
+    pub correlation: f32,
+}
+
+/// How far the level must rise above the silence threshold to end silence (dB)
+const SILENCE_HYSTERESIS_DB: f32 = 3.0;
+
+/// Reports when the audio goes quiet and when it comes back
+struct SilenceDetector {
+    threshold_db: f32,
+    hold: Duration,
+    /// Capture time of the first frame of the current quiet stretch
+    quiet_since: Option<Instant>,
+    silent: bool,
+}
+
+impl SilenceDetector {
+    fn new(threshold_db: f32, hold_ms: f32) -> Self {
+        Self {
+            threshold_db,
+            hold: Duration::from_secs_f32(hold_ms.max(0.0) / 1000.0),
+            quiet_since: None,
+            silent: false,
+        }
+    }
+    
+    /// Feed the RMS level of a frame captured at `timestamp`, returning an event if silence started or ended
+    fn update(&mut self, level: f32, timestamp: Instant) -> Option<AudioEvent> {
+        let level_db = 20.0 * level.max(1e-10).log10();
+        
+        if self.silent {
+            if level_db > self.threshold_db + SILENCE_HYSTERESIS_DB {
+                self.silent = false;
+                self.quiet_since = None;
+                return Some(AudioEvent::SilenceEnded);
+            }
+            return None;
+        }
+        
+        if level_db >= self.threshold_db {
+            self.quiet_since = None;
+            return None;
+        }
+        
+        let quiet_since = *self.quiet_since.get_or_insert(timestamp);
+        if timestamp.saturating_duration_since(quiet_since) >= self.hold {
+            self.silent = true;
+            return Some(AudioEvent::SilenceStarted);
+        }
+        None
+    }
+}
+
 /// Audio analyzer with FFT processing and feature extraction
 pub struct AudioAnalyzer {
     config: AudioConfig,
@@ -68,23 +173,57 @@ pub struct AudioAnalyzer {
     fft_planner: RealFftPlanner<f32>,
     fft_processor: Option<Arc<dyn RealToComplex<f32>>>,
     
-    // Buffers
+    // Resampled, levelled stereo samples short of a hop, waiting for the next frame
+    pending: Vec<f32>,
+    
+    // Analysis windows, shifted along one hop at a time
     input_buffer: Vec<f32>,
+    channel_buffers: [Vec<f32>; 2],
     fft_input: Vec<f32>,
     fft_output: Vec<Complex<f32>>,
     window: Vec<f32>,
     
+    // Analyses at other FFT sizes, merged into the spectra over their frequency ranges
+    resolutions: Vec<ResolutionAnalyzer>,
+    
     // History for beat detection and smoothing
     volume_history: VecDeque<f32>,
     bass_history: VecDeque<f32>,
+    history_size: usize,
     
     // State
     sample_rate: f32,
-    bin_frequencies: Vec<f32>,
+    /// Hops an analysis window spans: the last window that doesn't overlap the current one is this many frames back
+    window_hops: usize,
+    bin_frequencies: Arc<[f32]>,
     
     // Beat detection state
-    last_beat_time: std::time::Instant,
-    tempo_buffer: VecDeque<f32>,
+    last_beat_time: Option<std::time::Instant>,
+    
+    // Tempo and beat grid, from the onset envelope
+    beats: BeatTracker,
+    
+    // Spectral flux onsets per band
+    onsets: OnsetDetector,
+    
+    // MilkDrop's relative and attenuated levels
+    levels: LevelTracker,
+    
+    // Chroma and key estimation
+    chroma: ChromaTracker,
+    
+    // Configurable bands for spectrum bars and LED outputs
+    filterbank: Filterbank,
+    
+    // Converts frames captured at another rate to `config.sample_rate`
+    resampler: Option<SincResampler>,
+    
+    // Levels the input before feature extraction, if enabled
+    agc: Option<AutomaticGainControl>,
+    
+    // Silence detection and the events it raised since the last `take_events`
+    silence: SilenceDetector,
+    events: Vec<AudioEvent>,
 }
 
 impl AudioAnalyzer {
@@ -94,71 +233,166 @@ impl AudioAnalyzer {
         let fft_processor = fft_planner.plan_fft_forward(config.fft_size);
         
         // Pre-allocate buffers
-        let input_buffer = vec![0.0; config.buffer_size];
+        let window_size = config.window_size.min(config.fft_size);
+        let input_buffer = vec![0.0; window_size];
+        let channel_buffers = [vec![0.0; window_size], vec![0.0; window_size]];
         let fft_input = vec![0.0; config.fft_size];
         let fft_output = vec![Complex::new(0.0, 0.0); config.fft_size / 2 + 1];
         
-        // Create Hann window for better frequency resolution
-        let window = Self::create_hann_window(config.fft_size);
+        // Window the samples that get zero-padded to `fft_size`, not the padded length
+        let window = config.window_function.coefficients(window_size);
         
         // Calculate frequency bins
         let sample_rate = config.sample_rate as f32;
-        let bin_frequencies = (0..=config.fft_size / 2)
+        let bin_frequencies: Arc<[f32]> = (0..=config.fft_size / 2)
             .map(|i| i as f32 * sample_rate / config.fft_size as f32)
             .collect();
+        let hop_size = config.hop_size.max(1);
+        let window_hops = window_size.div_ceil(hop_size).max(1);
+        let onsets = OnsetDetector::new(&bin_frequencies, sample_rate / hop_size as f32, window_hops);
+        let levels = LevelTracker::new(&bin_frequencies, sample_rate);
+        let chroma = ChromaTracker::new(&bin_frequencies);
+        let filterbank = Filterbank::new(config, &bin_frequencies);
+        let resolutions = config.resolutions
+            .iter()
+            .map(|resolution| ResolutionAnalyzer::new(resolution, config, &mut fft_planner, &bin_frequencies))
+            .collect();
         
         // Initialize history buffers
-        let history_size = (sample_rate / config.buffer_size as f32 * 2.0) as usize; // ~2 seconds
+        // ~2 seconds, and always enough for `detect_beat` to look back over 10 windows
+        let history_size = ((sample_rate / hop_size as f32 * 2.0) as usize).max(10 * window_hops + 1);
         let volume_history = VecDeque::with_capacity(history_size);
         let bass_history = VecDeque::with_capacity(history_size);
-        let tempo_buffer = VecDeque::with_capacity(32); // Last 32 beat intervals
         
         Ok(Self {
             config: config.clone(),
             fft_planner,
             fft_processor: Some(fft_processor),
+            pending: Vec::new(),
             input_buffer,
+            channel_buffers,
             fft_input,
             fft_output,
             window,
+            resolutions,
             volume_history,
             bass_history,
+            history_size,
             sample_rate,
+            window_hops,
             bin_frequencies,
-            last_beat_time: std::time::Instant::now(),
-            tempo_buffer,
+            last_beat_time: None,
+            beats: BeatTracker::new(),
+            onsets,
+            levels,
+            chroma,
+            filterbank,
+            resampler: None,
+            agc: config.agc_enabled.then(|| AutomaticGainControl::new(config)),
+            silence: SilenceDetector::new(config.silence_threshold_db, config.silence_hold_ms),
+            events: Vec::new(),
         })
     }
     
-    /// Process an audio frame and return analyzed data
-    pub fn process_frame(&mut self, frame: &AudioFrame) -> Result<AudioData> {
-        // Convert multi-channel to mono by averaging
-        let mono_samples = self.convert_to_mono(&frame.samples, frame.channels);
+    /// Process an audio frame and return the data analyzed from it
+    ///
+    /// A window is analyzed every `hop_size` samples, so a frame gives as many
+    /// results as hops it completes: several for a long frame, none for a short
+    /// one. Samples left over wait for the next frame.
+    pub fn process_frame(&mut self, frame: &AudioFrame) -> Result<Vec<AudioData>> {
+        // Work on the stereo pair: mono is duplicated, wider layouts are downmixed
+        let stereo_samples = remix(&frame.samples, frame.channels, 2);
         
-        // Update input buffer (ring buffer behavior)
-        self.update_input_buffer(&mono_samples);
+        // Bring the samples to the analysis rate, so bin frequencies and band edges hold
+        let mut stereo_samples = self.resample(stereo_samples, frame.sample_rate);
         
-        // Prepare FFT input with windowing
-        self.prepare_fft_input();
+        // Silence is judged on the input level, before any gain is applied
+        let input_level = if stereo_samples.is_empty() {
+            0.0
+        } else {
+            (stereo_samples.iter().map(|sample| sample * sample).sum::<f32>() / stereo_samples.len() as f32).sqrt()
+        };
         
-        // Perform FFT
-        let spectrum = self.perform_fft()?;
+        // Level the input, so features look the same whatever the capture gain
+        if let Some(agc) = &mut self.agc {
+            agc.process(&mut stereo_samples, 2, self.config.sample_rate);
+        }
+        
+        // Follow the capture clock: samples still waiting were captured just before this frame
+        let waiting = Duration::from_secs_f32(self.pending.len() as f32 / 2.0 / self.sample_rate);
+        let mut hop_start = frame.timestamp.checked_sub(waiting).unwrap_or(frame.timestamp);
+        self.pending.extend_from_slice(&stereo_samples);
+        
+        // Analyze every complete hop
+        let hop_size = self.config.hop_size.max(1);
+        let hop_duration = Duration::from_secs_f32(hop_size as f32 / self.sample_rate);
+        let mut results = Vec::new();
+        while self.pending.len() >= hop_size * 2 {
+            let hop: Vec<f32> = self.pending.drain(..hop_size * 2).collect();
+            results.push(self.analyze_hop(&hop, hop_start)?);
+            hop_start += hop_duration;
+        }
+        
+        if let Some(event) = self.silence.update(input_level, frame.timestamp) {
+            self.events.push(event);
+        }
         
-        // Extract audio features
-        let features = self.extract_features(&mono_samples, &spectrum);
+        Ok(results)
+    }
+    
+    /// Shift a hop of stereo samples captured at `timestamp` into the windows and analyze them
+    fn analyze_hop(&mut self, stereo_samples: &[AudioSample], timestamp: Instant) -> Result<AudioData> {
+        // Convert to mono by averaging
+        let mono_samples = self.convert_to_mono(stereo_samples, 2);
+        let (left_samples, right_samples): (Vec<AudioSample>, Vec<AudioSample>) = stereo_samples
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .unzip();
+        
+        // Update input buffers (ring buffer behavior)
+        Self::update_input_buffer(&mut self.input_buffer, &mono_samples);
+        Self::update_input_buffer(&mut self.channel_buffers[0], &left_samples);
+        Self::update_input_buffer(&mut self.channel_buffers[1], &right_samples);
+        for resolution in &mut self.resolutions {
+            resolution.push([&mono_samples, &left_samples, &right_samples]);
+        }
+        
+        // Window and transform the mono mix, then each channel
+        Self::prepare_fft_input(&self.input_buffer, &self.window, &mut self.fft_input);
+        let spectrum = self.perform_fft(0)?;
+        Self::prepare_fft_input(&self.channel_buffers[0], &self.window, &mut self.fft_input);
+        let left_spectrum = self.perform_fft(1)?;
+        Self::prepare_fft_input(&self.channel_buffers[1], &self.window, &mut self.fft_input);
+        let right_spectrum = self.perform_fft(2)?;
+        
+        // Extract audio features over the whole window
+        let waveform = self.input_buffer.clone();
+        let [left_waveform, right_waveform] = self.channel_buffers.clone();
+        let features = self.extract_features(&waveform, &spectrum, timestamp);
+        let stereo = Self::extract_stereo_features(&left_waveform, &right_waveform);
         
         // Update history for beat detection
         self.update_history(&features);
         
         // Create processed audio data
         Ok(AudioData {
-            waveform: mono_samples,
+            waveform,
+            bands: self.filterbank.apply(&spectrum.bins),
             spectrum,
             features,
-            timestamp: frame.timestamp,
+            left: ChannelData { waveform: left_waveform, spectrum: left_spectrum },
+            right: ChannelData { waveform: right_waveform, spectrum: right_spectrum },
+            stereo,
+            timestamp,
+            queue_stats: AudioQueueStats::default(),
         })
     }
     
+    /// Events raised while processing frames since the last call (silence started or ended)
+    pub fn take_events(&mut self) -> Vec<AudioEvent> {
+        std::mem::take(&mut self.events)
+    }
+    
     /// Convert multi-channel audio to mono
     fn convert_to_mono(&self, samples: &[AudioSample], channels: u16) -> Vec<AudioSample> {
         if channels == 1 {
@@ -179,48 +413,67 @@ impl AudioAnalyzer {
         mono
     }
     
-    /// Update the input buffer with new samples
-    fn update_input_buffer(&mut self, new_samples: &[AudioSample]) {
-        let buffer_size = self.input_buffer.len();
+    /// Resample stereo samples captured at `sample_rate` to the configured sample rate
+    fn resample(&mut self, samples: Vec<AudioSample>, sample_rate: u32) -> Vec<AudioSample> {
+        if sample_rate == self.config.sample_rate || sample_rate == 0 {
+            self.resampler = None;
+            return samples;
+        }
+        
+        let resampler = match &mut self.resampler {
+            Some(resampler) if resampler.input_rate() == sample_rate => resampler,
+            resampler => {
+                log::info!("Resampling {} Hz audio to {} Hz for analysis", sample_rate, self.config.sample_rate);
+                resampler.insert(SincResampler::new(sample_rate, self.config.sample_rate, 2))
+            }
+        };
+        
+        resampler.process(&samples)
+    }
+    
+    /// Update an input buffer with new samples
+    pub(super) fn update_input_buffer(buffer: &mut [f32], new_samples: &[AudioSample]) {
+        let buffer_size = buffer.len();
         let new_size = new_samples.len();
         
         if new_size >= buffer_size {
             // Replace entire buffer
-            self.input_buffer.copy_from_slice(&new_samples[new_size - buffer_size..]);
+            buffer.copy_from_slice(&new_samples[new_size - buffer_size..]);
         } else {
             // Shift existing data and append new samples
-            self.input_buffer.copy_within(new_size.., 0);
+            buffer.copy_within(new_size.., 0);
             let start_idx = buffer_size - new_size;
-            self.input_buffer[start_idx..].copy_from_slice(new_samples);
+            buffer[start_idx..].copy_from_slice(new_samples);
         }
     }
     
-    /// Prepare FFT input with windowing
-    fn prepare_fft_input(&mut self) {
-        let buffer_size = self.input_buffer.len();
-        let fft_size = self.fft_input.len();
+    /// Prepare FFT input from an input buffer with windowing
+    pub(super) fn prepare_fft_input(input_buffer: &[f32], window: &[f32], fft_input: &mut [f32]) {
+        let buffer_size = input_buffer.len();
+        let fft_size = fft_input.len();
         
         // Zero-pad if needed or take the most recent samples
         if buffer_size >= fft_size {
             let start_idx = buffer_size - fft_size;
-            self.fft_input.copy_from_slice(&self.input_buffer[start_idx..]);
+            fft_input.copy_from_slice(&input_buffer[start_idx..]);
         } else {
             // Zero-pad at the beginning
             let pad_size = fft_size - buffer_size;
-            for i in 0..pad_size {
-                self.fft_input[i] = 0.0;
+            for sample in &mut fft_input[..pad_size] {
+                *sample = 0.0;
             }
-            self.fft_input[pad_size..].copy_from_slice(&self.input_buffer);
+            fft_input[pad_size..].copy_from_slice(input_buffer);
         }
         
-        // Apply Hann window
-        for (_i, (sample, window_val)) in self.fft_input.iter_mut().zip(self.window.iter()).enumerate() {
+        // Apply the window over the samples, not the zero padding
+        let window_start = fft_size - window.len();
+        for (sample, window_val) in fft_input[window_start..].iter_mut().zip(window.iter()) {
             *sample *= window_val;
         }
     }
     
-    /// Perform FFT and return frequency data
-    fn perform_fft(&mut self) -> Result<FrequencyData> {
+    /// Perform FFT and return frequency data, with the other resolutions' bins for `channel` (0 mono, 1 left, 2 right) merged in
+    fn perform_fft(&mut self, channel: usize) -> Result<FrequencyData> {
         // Perform FFT
         if let Some(ref fft_processor) = self.fft_processor {
             fft_processor.process(&mut self.fft_input, &mut self.fft_output)
@@ -230,9 +483,12 @@ impl AudioAnalyzer {
         }
         
         // Calculate magnitude spectrum
-        let bins: Vec<f32> = self.fft_output.iter()
+        let mut bins: Vec<f32> = self.fft_output.iter()
             .map(|c| c.norm() / self.config.fft_size as f32)
             .collect();
+        for resolution in &mut self.resolutions {
+            resolution.merge(channel, &mut bins)?;
+        }
         
         // Find peak frequency
         let peak_bin = bins.iter()
@@ -258,7 +514,7 @@ impl AudioAnalyzer {
         
         Ok(FrequencyData {
             bins,
-            bin_frequencies: self.bin_frequencies.clone(),
+            bin_frequencies: Arc::clone(&self.bin_frequencies),
             peak_frequency,
             spectral_centroid,
             spectral_energy,
@@ -266,7 +522,7 @@ impl AudioAnalyzer {
     }
     
     /// Extract audio features from time and frequency domain
-    fn extract_features(&mut self, waveform: &[AudioSample], spectrum: &FrequencyData) -> AudioFeatures {
+    fn extract_features(&mut self, waveform: &[AudioSample], spectrum: &FrequencyData, timestamp: std::time::Instant) -> AudioFeatures {
         // Calculate RMS volume
         let volume = if !waveform.is_empty() {
             (waveform.iter().map(|x| x * x).sum::<f32>() / waveform.len() as f32).sqrt()
@@ -292,10 +548,18 @@ impl AudioAnalyzer {
         let zero_crossing_rate = self.calculate_zero_crossing_rate(waveform);
         
         // Detect beats
-        let beat_confidence = self.detect_beat(volume, bass);
+        let beat_confidence = self.detect_beat(volume, bass, timestamp);
         
-        // Estimate tempo
-        let tempo = self.estimate_tempo();
+        // Detect transients in every band, not just the kick
+        let (onset_strength, onsets) = self.onsets.process(&spectrum.bins, timestamp);
+        
+        // Track tempo and beat phase
+        let grid = self.beats.update(self.onsets.novelty(), timestamp);
+        
+        // Levels relative to the long-term average, for presets
+        let hop_duration = self.config.hop_size as f32 / self.sample_rate;
+        let milkdrop = self.levels.update(&spectrum.bins, hop_duration);
+        let harmony = self.chroma.update(&spectrum.bins, hop_duration);
         
         AudioFeatures {
             volume,
@@ -308,13 +572,54 @@ impl AudioAnalyzer {
             presence,
             brilliance,
             beat_confidence,
-            tempo,
+            onset_strength,
+            onsets,
+            tempo: grid.tempo,
+            tempo_confidence: grid.tempo_confidence,
+            beat_phase: grid.beat_phase,
+            bar_phase: grid.bar_phase,
+            milkdrop,
+            harmony,
             zero_crossing_rate,
             spectral_centroid: spectrum.spectral_centroid,
             spectral_rolloff: self.calculate_spectral_rolloff(spectrum),
         }
     }
     
+    /// Measure width, balance and phase correlation of a stereo pair
+    fn extract_stereo_features(left: &[AudioSample], right: &[AudioSample]) -> StereoFeatures {
+        fn energy(samples: impl Iterator<Item = f32>) -> f32 {
+            samples.map(|x| x * x).sum()
+        }
+        
+        let left_energy = energy(left.iter().copied());
+        let right_energy = energy(right.iter().copied());
+        let mid_energy = energy(left.iter().zip(right).map(|(l, r)| (l + r) * 0.5));
+        let side_energy = energy(left.iter().zip(right).map(|(l, r)| (l - r) * 0.5));
+        let cross: f32 = left.iter().zip(right).map(|(l, r)| l * r).sum();
+        
+        // Silence counts as centered mono
+        let (mid, side) = (mid_energy.sqrt(), side_energy.sqrt());
+        let width = if mid + side > 0.0 { side / (mid + side) } else { 0.0 };
+        
+        let (left_level, right_level) = (left_energy.sqrt(), right_energy.sqrt());
+        let balance = if left_level + right_level > 0.0 {
+            (right_level - left_level) / (left_level + right_level)
+        } else {
+            0.0
+        };
+        
+        let correlation = if left_energy > 0.0 && right_energy > 0.0 {
+            (cross / (left_energy * right_energy).sqrt()).clamp(-1.0, 1.0)
+        } else if left_energy + right_energy > 0.0 {
+            0.0
+        } else {
+            1.0
+        };
+        
+        StereoFeatures { width, balance, correlation }
+    }
+    
     /// Extract energy from a specific frequency band
     fn extract_frequency_band(&self, spectrum: &FrequencyData, low_freq: f32, high_freq: f32) -> f32 {
         let nyquist = self.sample_rate / 2.0;
@@ -366,19 +671,24 @@ impl AudioAnalyzer {
         let crossings = waveform.windows(2)
             .filter(|window| window[0] * window[1] < 0.0)
             .count();
-            
+        
         crossings as f32 / (waveform.len() - 1) as f32
     }
     
     /// Simple beat detection based on energy changes
-    fn detect_beat(&mut self, current_volume: f32, current_bass: f32) -> f32 {
+    ///
+    /// Beats are spaced by the frame timestamps rather than the wall clock, so
+    /// sources that run faster than real time are judged the same.
+    fn detect_beat(&mut self, current_volume: f32, current_bass: f32, timestamp: std::time::Instant) -> f32 {
         let mut beat_confidence = 0.0;
         
         // Check if we have enough history
-        if self.volume_history.len() > 10 && self.bass_history.len() > 10 {
-            // Calculate recent average
-            let recent_avg: f32 = self.volume_history.iter().rev().take(5).sum::<f32>() / 5.0;
-            let bass_avg: f32 = self.bass_history.iter().rev().take(5).sum::<f32>() / 5.0;
+        let hops = self.window_hops;
+        if self.volume_history.len() > 10 * hops && self.bass_history.len() > 10 * hops {
+            // Calculate recent average, over the last windows that don't overlap this one
+            let recent = |history: &VecDeque<f32>| history.iter().rev().skip(hops - 1).step_by(hops).take(5).sum::<f32>() / 5.0;
+            let recent_avg = recent(&self.volume_history);
+            let bass_avg = recent(&self.bass_history);
             
             // Beat detection: current energy significantly higher than recent average
             let volume_ratio = if recent_avg > 0.0 { current_volume / recent_avg } else { 1.0 };
@@ -386,21 +696,17 @@ impl AudioAnalyzer {
             
             // Combine volume and bass energy for beat detection
             if volume_ratio > 1.5 && bass_ratio > 1.3 {
-                let now = std::time::Instant::now();
-                let time_since_last = now.duration_since(self.last_beat_time).as_secs_f32();
+                let time_since_last = self.last_beat_time
+                    .map(|last| timestamp.duration_since(last).as_secs_f32())
+                    .unwrap_or(f32::MAX);
                 
                 // Avoid detecting beats too frequently (minimum 100ms apart)
                 if time_since_last > 0.1 {
                     beat_confidence = ((volume_ratio - 1.5) + (bass_ratio - 1.3)) / 2.0;
                     beat_confidence = beat_confidence.min(1.0);
                     
-                    // Record beat timing for tempo estimation
                     if beat_confidence > 0.3 {
-                        self.tempo_buffer.push_back(time_since_last);
-                        if self.tempo_buffer.len() > 32 {
-                            self.tempo_buffer.pop_front();
-                        }
-                        self.last_beat_time = now;
+                        self.last_beat_time = Some(timestamp);
                     }
                 }
             }
@@ -409,23 +715,6 @@ impl AudioAnalyzer {
         beat_confidence
     }
     
-    /// Estimate tempo from beat intervals
-    fn estimate_tempo(&self) -> f32 {
-        if self.tempo_buffer.len() < 4 {
-            return 0.0;
-        }
-        
-        // Calculate average beat interval
-        let avg_interval: f32 = self.tempo_buffer.iter().sum::<f32>() / self.tempo_buffer.len() as f32;
-        
-        // Convert to BPM
-        if avg_interval > 0.0 {
-            60.0 / avg_interval
-        } else {
-            0.0
-        }
-    }
-    
     /// Update history buffers for beat detection
     fn update_history(&mut self, features: &AudioFeatures) {
         // Add to history
@@ -433,31 +722,20 @@ impl AudioAnalyzer {
         self.bass_history.push_back(features.bass);
         
         // Limit history size
-        let max_history = 100; // Keep ~2 seconds of history at typical buffer rates
-        if self.volume_history.len() > max_history {
+        if self.volume_history.len() > self.history_size {
             self.volume_history.pop_front();
         }
-        if self.bass_history.len() > max_history {
+        if self.bass_history.len() > self.history_size {
             self.bass_history.pop_front();
         }
     }
     
-    /// Create a Hann window for FFT
-    fn create_hann_window(size: usize) -> Vec<f32> {
-        (0..size)
-            .map(|i| {
-                let phase = 2.0 * std::f32::consts::PI * i as f32 / (size - 1) as f32;
-                0.5 * (1.0 - phase.cos())
-            })
-            .collect()
-    }
-
     /// Calculate spectral rolloff (frequency below which 85% of energy is contained)
     fn calculate_spectral_rolloff(&self, spectrum: &FrequencyData) -> f32 {
         let energy_threshold = spectrum.spectral_energy * 0.85; // 85% of total energy
         let mut cumulative_energy = 0.0;
         let mut rolloff_bin = 0;
-
+        
         for (i, &mag) in spectrum.bins.iter().enumerate() {
             cumulative_energy += mag;
             if cumulative_energy >= energy_threshold {
@@ -465,12 +743,12 @@ impl AudioAnalyzer {
                 break;
             }
         }
-
+        
         // If no bin reaches 85%, return the highest frequency bin
         if rolloff_bin == 0 {
             rolloff_bin = spectrum.bins.len() - 1;
         }
-
+        
         spectrum.bin_frequencies[rolloff_bin]
     }
 }
@@ -478,7 +756,51 @@ impl AudioAnalyzer {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::AudioCaptureMode;
+    use crate::audio::synthetic::SignalGenerator;
+    use crate::config::{AudioCaptureMode, SpectrumResolution, TestSignal, WindowFunction};
+    
+    /// Run `seconds` of a synthetic test signal through a fresh analyzer
+    fn analyze_signal(signal: TestSignal, seconds: f32) -> Vec<AudioFeatures> {
+        analyze_signal_at(signal, seconds, 44100)
+            .into_iter()
+            .map(|data| data.features)
+            .collect()
+    }
+    
+    /// Run a test signal captured at `capture_rate` through a 44.1 kHz analyzer
+    fn analyze_signal_at(signal: TestSignal, seconds: f32, capture_rate: u32) -> Vec<AudioData> {
+        let config = AudioConfig {
+            sample_rate: 44100,
+            buffer_size: 1024,
+            fft_size: 2048,
+            ..AudioConfig::default()
+        };
+        analyze_signal_with(&config, signal, seconds, capture_rate)
+    }
+        
+    /// Run a test signal captured at `capture_rate` through an analyzer with `config`
+    fn analyze_signal_with(config: &AudioConfig, signal: TestSignal, seconds: f32, capture_rate: u32) -> Vec<AudioData> {
+        let mut analyzer = AudioAnalyzer::new(config).unwrap();
+        let mut generator = SignalGenerator::new(signal, 0.5, capture_rate);
+        let start = std::time::Instant::now();
+        let frame_count = (seconds * capture_rate as f32 / config.buffer_size as f32) as usize;
+        
+        (0..frame_count)
+            .flat_map(|_| {
+                let offset = generator.position() as f64 / capture_rate as f64;
+                let mut samples = vec![0.0; config.buffer_size];
+                generator.fill(&mut samples);
+                
+                let frame = AudioFrame {
+                    samples,
+                    timestamp: start + std::time::Duration::from_secs_f64(offset),
+                    sample_rate: capture_rate,
+                    channels: 1,
+                };
+                analyzer.process_frame(&frame).unwrap()
+            })
+            .collect()
+    }
     
     #[test]
     fn test_audio_analyzer_creation() {
@@ -490,6 +812,7 @@ mod tests {
             capture_mode: AudioCaptureMode::Input,
             enable_loopback: false,
             target_latency_ms: 50.0,
+            ..AudioConfig::default()
         };
         
         let analyzer = AudioAnalyzer::new(&config);
@@ -506,6 +829,7 @@ mod tests {
             capture_mode: AudioCaptureMode::Input,
             enable_loopback: false,
             target_latency_ms: 50.0,
+            ..AudioConfig::default()
         };
         
         let analyzer = AudioAnalyzer::new(&config).unwrap();
@@ -521,7 +845,7 @@ mod tests {
     
     #[test]
     fn test_hann_window() {
-        let window = AudioAnalyzer::create_hann_window(8);
+        let window = WindowFunction::Hann.coefficients(8);
         assert_eq!(window.len(), 8);
         
         // Hann window should start and end at 0
@@ -532,4 +856,340 @@ mod tests {
         let max_val = window.iter().fold(0.0f32, |acc, &x| acc.max(x));
         assert!((max_val - 1.0).abs() < 0.1);
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_sine_band_energies() {
+        let bass_tone = analyze_signal(TestSignal::Sine { frequency: 100.0 }, 0.5);
+        let bass_features = bass_tone.last().unwrap();
+        assert!(bass_features.bass > bass_features.mid);
+        assert!(bass_features.bass > bass_features.brilliance);
+        
+        let high_tone = analyze_signal(TestSignal::Sine { frequency: 3000.0 }, 0.5);
+        let high_features = high_tone.last().unwrap();
+        assert!(high_features.high_mid > high_features.bass);
+        assert!(high_features.high_mid > high_features.presence);
+        
+        // RMS of a sine is amplitude / sqrt(2)
+        assert!((high_features.volume - 0.5 / 2.0f32.sqrt()).abs() < 0.01);
+    }
+    
+    #[test]
+    fn test_resolutions_sharpen_the_bass() {
+        let plain = AudioConfig { sample_rate: 44100, buffer_size: 1024, fft_size: 2048, ..AudioConfig::default() };
+        let layered = AudioConfig {
+            resolutions: vec![SpectrumResolution { fft_size: 8192, window_size: 8192, min_hz: 0.0, max_hz: 300.0 }],
+            ..plain.clone()
+        };
+        
+        // A tone on a bin of both FFTs, low enough for the long one
+        let tone = TestSignal::Sine { frequency: 44100.0 * 5.0 / 2048.0 };
+        let plain = analyze_signal_with(&plain, tone.clone(), 1.0, 44100).pop().unwrap().spectrum;
+        let layered = analyze_signal_with(&layered, tone, 1.0, 44100).pop().unwrap().spectrum;
+        
+        // Same peak at the same level, but much narrower
+        assert_eq!(plain.peak_frequency, layered.peak_frequency);
+        let (plain_peak, layered_peak) = (plain.bins[5], layered.bins[5]);
+        assert!((layered_peak - plain_peak).abs() < plain_peak * 0.05, "{} vs {}", layered_peak, plain_peak);
+        let width = |bins: &[f32], peak: f32| bins.iter().filter(|&&bin| bin > peak / 2.0).count();
+        assert!(width(&layered.bins, layered_peak) < width(&plain.bins, plain_peak));
+        
+        // Above its range the main FFT is left alone
+        for ((frequency, plain), layered) in plain.bin_frequencies.iter().zip(&plain.bins).zip(&layered.bins) {
+            if *frequency >= 300.0 {
+                assert_eq!(plain, layered, "{} Hz", frequency);
+            }
+        }
+    }
+    
+    #[test]
+    fn test_tone_pitch_class() {
+        // A5 is pitch class 9, read from its own bins as it's above where bins get closer than a semitone
+        let features = analyze_signal(TestSignal::Sine { frequency: 880.0 }, 1.0);
+        let harmony = features.last().unwrap().harmony;
+        assert_eq!(harmony.pitch_class, 9);
+        assert!(harmony.chroma[9] > 0.9, "{:?}", harmony.chroma);
+    }
+    
+    #[test]
+    fn test_other_capture_rates_are_resampled() {
+        // Without resampling, 1 kHz captured at 48 kHz would show up at 919 Hz
+        for capture_rate in [48000, 96000, 22050] {
+            let data = analyze_signal_at(TestSignal::Sine { frequency: 1000.0 }, 0.5, capture_rate);
+            let peak = data.last().unwrap().spectrum.peak_frequency;
+            assert!((peak - 1000.0).abs() < 44100.0 / 2048.0, "{} Hz capture peaked at {} Hz", capture_rate, peak);
+        }
+    }
+    
+    #[test]
+    fn test_stereo_features() {
+        let config = AudioConfig::default();
+        let mut analyzer = AudioAnalyzer::new(&config).unwrap();
+        let tone: Vec<f32> = (0..config.buffer_size)
+            .map(|n| (2.0 * std::f32::consts::PI * 1000.0 * n as f32 / config.sample_rate as f32).sin() * 0.5)
+            .collect();
+        let mut stereo_frame = |right: &dyn Fn(f32) -> f32| {
+            let frame = AudioFrame {
+                samples: tone.iter().flat_map(|&sample| [sample, right(sample)]).collect(),
+                timestamp: std::time::Instant::now(),
+                sample_rate: config.sample_rate,
+                channels: 2,
+            };
+            // The last hop's window holds the whole frame
+            analyzer.process_frame(&frame).unwrap().pop().unwrap()
+        };
+        
+        // Same signal on both sides
+        let data = stereo_frame(&|sample| sample);
+        assert!(data.stereo.width.abs() < 1e-6);
+        assert!(data.stereo.balance.abs() < 1e-6);
+        assert!((data.stereo.correlation - 1.0).abs() < 1e-6);
+        assert_eq!(data.left.waveform, data.right.waveform);
+        assert_eq!(data.left.spectrum.peak_frequency, data.right.spectrum.peak_frequency);
+        
+        // Polarity flipped on the right
+        let data = stereo_frame(&|sample| -sample);
+        assert!((data.stereo.width - 1.0).abs() < 1e-6);
+        assert!((data.stereo.correlation + 1.0).abs() < 1e-6);
+        assert!(data.waveform.iter().all(|&sample| sample.abs() < 1e-6));
+        
+        // Left only
+        let data = stereo_frame(&|_| 0.0);
+        assert!((data.stereo.balance + 1.0).abs() < 1e-6);
+        assert_eq!(data.stereo.correlation, 0.0);
+        assert_eq!(data.left.waveform, tone);
+        assert!(data.right.spectrum.bins.iter().all(|&bin| bin == 0.0));
+    }
+    
+    #[test]
+    fn test_hops_do_not_depend_on_frame_size() {
+        let config = AudioConfig::default();
+        let mut generator = SignalGenerator::new(TestSignal::Kick { bpm: 120.0 }, 0.5, config.sample_rate);
+        let mut samples = vec![0.0; config.sample_rate as usize];
+        generator.fill(&mut samples);
+        let start = Instant::now();
+        
+        let analyze = |frame_size: usize| {
+            let mut analyzer = AudioAnalyzer::new(&config).unwrap();
+            samples
+                .chunks(frame_size)
+                .enumerate()
+                .flat_map(|(index, chunk)| {
+                    let offset = (index * frame_size) as f64 / config.sample_rate as f64;
+                    let frame = AudioFrame {
+                        samples: chunk.to_vec(),
+                        timestamp: start + Duration::from_secs_f64(offset),
+                        sample_rate: config.sample_rate,
+                        channels: 1,
+                    };
+                    analyzer.process_frame(&frame).unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+        
+        // A second of audio is 172 hops, whether it comes 100 or 3000 samples at a time
+        let small = analyze(100);
+        let large = analyze(3000);
+        assert_eq!(small.len(), config.sample_rate as usize / config.hop_size);
+        assert_eq!(small.len(), large.len());
+        for (index, (a, b)) in small.iter().zip(&large).enumerate() {
+            let expected = start + Duration::from_secs_f64((index * config.hop_size) as f64 / config.sample_rate as f64);
+            assert!(a.timestamp.max(expected) - a.timestamp.min(expected) < Duration::from_micros(10));
+            assert!(b.timestamp.max(expected) - b.timestamp.min(expected) < Duration::from_micros(10));
+            assert_eq!(a.waveform, b.waveform);
+            assert_eq!(a.features.volume, b.features.volume);
+        }
+    }
+    
+    #[test]
+    fn test_silence_has_no_features() {
+        let features = analyze_signal(TestSignal::Silence, 0.5);
+        let last = features.last().unwrap();
+        assert_eq!(last.volume, 0.0);
+        assert_eq!(last.bass, 0.0);
+        assert!(features.iter().all(|f| f.beat_confidence == 0.0));
+    }
+    
+    #[test]
+    fn test_silence_events() {
+        let config = AudioConfig {
+            silence_threshold_db: -60.0,
+            silence_hold_ms: 500.0,
+            ..AudioConfig::default()
+        };
+        let mut analyzer = AudioAnalyzer::new(&config).unwrap();
+        let start = Instant::now();
+        let frame_duration = config.buffer_size as f32 / config.sample_rate as f32;
+        let mut frame_index = 0;
+        let mut process = |level: f32, seconds: f32| {
+            let mut events = Vec::new();
+            for _ in 0..(seconds / frame_duration) as usize {
+                let frame = AudioFrame {
+                    samples: vec![level; config.buffer_size],
+                    timestamp: start + Duration::from_secs_f32(frame_index as f32 * frame_duration),
+                    sample_rate: config.sample_rate,
+                    channels: 1,
+                };
+                frame_index += 1;
+                analyzer.process_frame(&frame).unwrap();
+                events.extend(analyzer.take_events());
+            }
+            events
+        };
+        
+        // Quiet gaps shorter than the hold time are not silence
+        assert!(process(0.1, 1.0).is_empty());
+        assert!(process(0.0, 0.3).is_empty());
+        assert!(process(0.1, 0.5).is_empty());
+        
+        // A -70 dB noise floor held long enough is
+        let events = process(10f32.powf(-70.0 / 20.0), 1.0);
+        assert!(matches!(events.as_slice(), [AudioEvent::SilenceStarted]));
+        
+        // Just above the threshold is not enough to end it, music is
+        assert!(process(10f32.powf(-59.0 / 20.0), 0.5).is_empty());
+        let events = process(0.1, 0.1);
+        assert!(matches!(events.as_slice(), [AudioEvent::SilenceEnded]));
+    }
+    
+    #[test]
+    fn test_agc_evens_out_input_levels() {
+        let config = AudioConfig {
+            agc_enabled: true,
+            ..AudioConfig::default()
+        };
+        let bass_at = |amplitude: f32| {
+            let mut analyzer = AudioAnalyzer::new(&config).unwrap();
+            let mut generator = SignalGenerator::new(TestSignal::Sine { frequency: 100.0 }, amplitude, config.sample_rate);
+            let mut features = None;
+            for _ in 0..20 {
+                let mut samples = vec![0.0; config.buffer_size];
+                generator.fill(&mut samples);
+                let frame = AudioFrame {
+                    samples,
+                    timestamp: Instant::now(),
+                    sample_rate: config.sample_rate,
+                    channels: 1,
+                };
+                features = analyzer.process_frame(&frame).unwrap().pop().map(|data| data.features);
+            }
+            features.unwrap()
+        };
+        
+        // A 26 dB difference in capture gain makes no difference to the features
+        let quiet = bass_at(0.025);
+        let loud = bass_at(0.5);
+        assert!((quiet.volume - loud.volume).abs() < 0.01 * loud.volume);
+        assert!((quiet.bass - loud.bass).abs() < 0.01 * loud.bass);
+    }
+    
+    #[test]
+    fn test_kick_beats_and_tempo() {
+        let features = analyze_signal(TestSignal::Kick { bpm: 120.0 }, 6.0);
+        
+        // One beat every half second, except the first which has too little history
+        let beats = features.iter().filter(|f| f.beat_confidence > 0.3).count();
+        assert!((10..=12).contains(&beats), "detected {} beats", beats);
+        
+        let last = features.last().unwrap();
+        assert!((last.tempo - 120.0).abs() < 1.0, "estimated tempo {}", last.tempo);
+        assert!(last.tempo_confidence > 0.5);
+        
+        // Once the grid has locked, kicks land on the beat
+        for f in features[features.len() / 2..].iter().filter(|f| f.onsets.bass) {
+            assert!(f.beat_phase.min(1.0 - f.beat_phase) < 0.1, "kick at beat phase {}", f.beat_phase);
+        }
+    }
+    
+    #[test]
+    fn test_beats_with_long_overlapping_windows() {
+        // 16 hops per window, the history must still reach back over 10 windows
+        let config = AudioConfig { fft_size: 4096, window_size: 4096, hop_size: 256, ..AudioConfig::default() };
+        let features = analyze_signal_with(&config, TestSignal::Kick { bpm: 120.0 }, 6.0, 44100);
+        assert!(features.iter().any(|data| data.features.beat_confidence > 0.3));
+    }
+    
+    #[test]
+    fn test_hi_hat_onsets_without_bass() {
+        let config = AudioConfig::default();
+        let mut analyzer = AudioAnalyzer::new(&config).unwrap();
+        let mut noise = SignalGenerator::new(TestSignal::WhiteNoise, 0.3, config.sample_rate);
+        let start = Instant::now();
+        
+        // Differenced noise with a short decay every quarter second: a closed hi-hat
+        let mut previous = 0.0;
+        let mut hi_hat = |n: usize| {
+            let mut sample = [0.0];
+            noise.fill(&mut sample);
+            let bright = sample[0] - previous;
+            previous = sample[0];
+            let since_hit = (n % (config.sample_rate as usize / 4)) as f32 / config.sample_rate as f32;
+            bright * (-since_hit / 0.01).exp()
+        };
+        
+        let features: Vec<AudioFeatures> = (0..86)
+            .flat_map(|index| {
+                let frame = AudioFrame {
+                    samples: (0..config.buffer_size).map(|n| hi_hat(index * config.buffer_size + n)).collect(),
+                    timestamp: start + Duration::from_secs_f64((index * config.buffer_size) as f64 / config.sample_rate as f64),
+                    sample_rate: config.sample_rate,
+                    channels: 1,
+                };
+                analyzer.process_frame(&frame).unwrap().into_iter().map(|data| data.features)
+            })
+            .collect();
+        
+        // Two seconds hold eight hits: every one is an onset up high, the first too
+        // as it rises out of silence, and none shows up in the bass
+        let hits = features.iter().filter(|f| f.onsets.brilliance).count();
+        assert_eq!(hits, 8);
+        assert!(features.iter().all(|f| !f.onsets.sub_bass && !f.onsets.bass));
+        
+        // Once the threshold has a second of history, they stand well clear of it
+        let second = config.sample_rate as usize / config.hop_size;
+        assert!(features[second..].iter().filter(|f| f.onsets.brilliance).all(|f| f.onset_strength > 0.3));
+        
+        // A steady tone has no onsets once it has started
+        let tone = analyze_signal(TestSignal::Sine { frequency: 1000.0 }, 1.0);
+        let window_hops = config.window_size / config.hop_size;
+        assert!(tone[2 * window_hops..].iter().all(|f| f.onsets == BandOnsets::default() && f.onset_strength == 0.0));
+    }
+    
+    #[test]
+    fn test_milkdrop_levels_are_relative() {
+        let config = AudioConfig::default();
+        let levels_at = |amplitude: f32| {
+            let mut analyzer = AudioAnalyzer::new(&config).unwrap();
+            let mut generator = SignalGenerator::new(TestSignal::Kick { bpm: 120.0 }, amplitude, config.sample_rate);
+            let frames = 20 * config.sample_rate as usize / config.buffer_size;
+            (0..frames)
+                .flat_map(|_| {
+                    let mut samples = vec![0.0; config.buffer_size];
+                    generator.fill(&mut samples);
+                    let frame = AudioFrame {
+                        samples,
+                        timestamp: Instant::now(),
+                        sample_rate: config.sample_rate,
+                        channels: 1,
+                    };
+                    analyzer.process_frame(&frame).unwrap().into_iter().map(|data| data.features.milkdrop)
+                })
+                .skip(frames * config.buffer_size / config.hop_size / 2)
+                .collect::<Vec<_>>()
+        };
+        let mean = |values: &[f32]| values.iter().sum::<f32>() / values.len() as f32;
+        let jitter = |values: &[f32]| mean(&values.windows(2).map(|pair| (pair[1] - pair[0]).abs()).collect::<Vec<_>>());
+        
+        // Loud or quiet, the song's own average reads as 1.0
+        let loud = levels_at(0.5);
+        let quiet = levels_at(0.02);
+        let loud_bass: Vec<f32> = loud.iter().map(|levels| levels.bass).collect();
+        let quiet_bass: Vec<f32> = quiet.iter().map(|levels| levels.bass).collect();
+        assert!((mean(&loud_bass) - 1.0).abs() < 0.15, "loud bass averages {}", mean(&loud_bass));
+        assert!((mean(&quiet_bass) - mean(&loud_bass)).abs() < 0.05);
+        
+        // Kicks stand out, the attenuated level follows them more smoothly
+        let bass_att: Vec<f32> = loud.iter().map(|levels| levels.bass_att).collect();
+        assert!(loud_bass.iter().any(|&bass| bass > 3.0));
+        assert!(jitter(&bass_att) < 0.8 * jitter(&loud_bass));
+    }
+}
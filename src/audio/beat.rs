@@ -0,0 +1,415 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Slowest tempo considered (BPM)
+const MIN_TEMPO: f32 = 60.0;
+
+/// Fastest tempo considered (BPM)
+const MAX_TEMPO: f32 = 200.0;
+
+/// Tempo the estimate leans towards when several multiples of the beat fit (BPM)
+const PREFERRED_TEMPO: f32 = 120.0;
+
+/// Width of that preference (octaves)
+const TEMPO_PRIOR_OCTAVES: f32 = 1.0;
+
+/// Length of onset envelope the tempo is estimated from (seconds)
+const ENVELOPE_SECONDS: f32 = 6.0;
+
+/// Frame intervals averaged to find the envelope sample rate
+const INTERVAL_HISTORY: usize = 64;
+
+/// Time either side each onset is spread over before autocorrelation (seconds)
+const SMOOTHING_SECONDS: f32 = 0.05;
+
+/// Time between tempo estimates (seconds), the autocorrelation is the costly part
+const TEMPO_INTERVAL: f32 = 0.05;
+
+/// Resolution of the candidate beat periods (frames)
+const LAG_STEP: f32 = 0.25;
+
+/// Multiples of the beat period summed by the comb filter
+const COMB_HARMONICS: usize = 4;
+
+/// Beats the phase estimate looks back over
+const PHASE_BEATS: usize = 4;
+
+/// Time constant the measured phase error is corrected with (seconds)
+const PHASE_TIME_CONSTANT: f32 = 0.2;
+
+/// Relative difference under which a new estimate is the same tempo, refined
+const TEMPO_TOLERANCE: f32 = 0.04;
+
+/// Time constant a same-tempo estimate is followed with (seconds)
+const TEMPO_TIME_CONSTANT: f32 = 0.2;
+
+/// Time a different tempo has to persist before the tracker switches to it (seconds)
+const TEMPO_SWITCH_SECONDS: f32 = 2.0;
+
+/// Confidence below which the grid runs on at its tempo instead of following the onsets
+const MIN_CONFIDENCE: f32 = 0.1;
+
+/// Beats per bar, the bar phase assumes 4/4
+const BEATS_PER_BAR: usize = 4;
+
+/// Start of each beat whose onsets count towards finding the downbeat (beats)
+const DOWNBEAT_WINDOW: f32 = 0.25;
+
+/// Margin by which another beat of the bar has to be stronger to become the downbeat
+const DOWNBEAT_MARGIN: f32 = 1.1;
+
+/// Where the music is on its beat grid
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BeatGrid {
+    /// Tempo (BPM), 0.0 until one has been found
+    pub tempo: f32,
+    
+    /// How periodic the onsets are at that tempo (0.0 - 1.0)
+    pub tempo_confidence: f32,
+    
+    /// Position within the current beat, 0.0 on the beat (0.0 - 1.0)
+    pub beat_phase: f32,
+    
+    /// Position within the current 4/4 bar, 0.0 on the downbeat (0.0 - 1.0)
+    pub bar_phase: f32,
+}
+
+/// Tempo and beat phase tracker
+///
+/// Estimates the tempo from the autocorrelation of the last few seconds of
+/// onset envelope, summed over multiples of each candidate period (a comb
+/// filter) and weighted towards moderate tempos so the estimate doesn't jump
+/// between octaves. A phase accumulator runs at that tempo and is pulled
+/// towards where the onsets put the beats, so the grid stays locked between
+/// onsets and through breaks. The downbeat is the beat of the bar with the
+/// strongest onsets.
+pub struct BeatTracker {
+    /// Onset envelope, one value per frame, oldest first
+    envelope: VecDeque<f32>,
+    intervals: VecDeque<f32>,
+    last_timestamp: Option<Instant>,
+    /// Time since the tempo was last estimated (seconds)
+    since_estimate: f32,
+    
+    tempo: f32,
+    confidence: f32,
+    /// A tempo that disagrees with `tempo` and how long it has (seconds)
+    candidate: Option<(f32, f32)>,
+    
+    phase: f32,
+    beat_count: u64,
+    /// Onsets at the start of the current beat
+    beat_energy: f32,
+    /// Decaying onset energy of each beat in the bar
+    bar_energy: [f32; BEATS_PER_BAR],
+    downbeat: usize,
+}
+
+impl BeatTracker {
+    /// Create a tracker that has not found a tempo yet
+    pub fn new() -> Self {
+        Self {
+            envelope: VecDeque::new(),
+            intervals: VecDeque::with_capacity(INTERVAL_HISTORY + 1),
+            last_timestamp: None,
+            since_estimate: 0.0,
+            tempo: 0.0,
+            confidence: 0.0,
+            candidate: None,
+            phase: 0.0,
+            beat_count: 0,
+            beat_energy: 0.0,
+            bar_energy: [0.0; BEATS_PER_BAR],
+            downbeat: 0,
+        }
+    }
+    
+    /// Feed the onset envelope value of a frame captured at `timestamp`
+    pub fn update(&mut self, onset: f32, timestamp: Instant) -> BeatGrid {
+        let elapsed = self.last_timestamp
+            .map_or(0.0, |last| timestamp.saturating_duration_since(last).as_secs_f32());
+        self.last_timestamp = Some(timestamp);
+        if elapsed > 0.0 {
+            self.intervals.push_back(elapsed);
+            if self.intervals.len() > INTERVAL_HISTORY {
+                self.intervals.pop_front();
+            }
+        }
+        
+        // The envelope is sampled once per frame
+        let frame_period = if self.intervals.is_empty() {
+            0.0
+        } else {
+            self.intervals.iter().sum::<f32>() / self.intervals.len() as f32
+        };
+        self.envelope.push_back(onset);
+        if frame_period > 0.0 {
+            let capacity = (ENVELOPE_SECONDS / frame_period).ceil() as usize;
+            while self.envelope.len() > capacity.max(1) {
+                self.envelope.pop_front();
+            }
+            
+            self.since_estimate += elapsed;
+            if self.since_estimate >= TEMPO_INTERVAL {
+                self.estimate_tempo(frame_period, self.since_estimate);
+                self.since_estimate = 0.0;
+            }
+        }
+        
+        self.advance(frame_period, elapsed, onset);
+        self.grid()
+    }
+    
+    /// Current position on the beat grid
+    pub fn grid(&self) -> BeatGrid {
+        if self.tempo <= 0.0 {
+            return BeatGrid::default();
+        }
+        
+        let beat_in_bar = (self.beat_count as usize % BEATS_PER_BAR + BEATS_PER_BAR - self.downbeat) % BEATS_PER_BAR;
+        BeatGrid {
+            tempo: self.tempo,
+            tempo_confidence: self.confidence,
+            beat_phase: self.phase,
+            bar_phase: (beat_in_bar as f32 + self.phase) / BEATS_PER_BAR as f32,
+        }
+    }
+    
+    /// Pick the best comb-filtered autocorrelation lag and follow it
+    fn estimate_tempo(&mut self, frame_period: f32, elapsed: f32) {
+        let min_lag = ((60.0 / MAX_TEMPO / frame_period).floor() as usize).max(1);
+        let max_lag = (60.0 / MIN_TEMPO / frame_period).ceil() as usize;
+        let count = self.envelope.len();
+        if count < 2 * max_lag {
+            return;
+        }
+        
+        // Spread each onset over its neighbours, so a period that falls between
+        // frames still lines up with itself
+        let spread = ((SMOOTHING_SECONDS / frame_period).round() as usize).max(1);
+        let smoothed: Vec<f32> = (0..count)
+            .map(|index| {
+                let (start, end) = (index.saturating_sub(spread), (index + spread + 1).min(count));
+                (start..end)
+                    .map(|near| self.envelope[near] * (spread + 1 - near.abs_diff(index)) as f32)
+                    .sum::<f32>()
+            })
+            .collect();
+        let mean = smoothed.iter().sum::<f32>() / count as f32;
+        let envelope: Vec<f32> = smoothed.iter().map(|value| value - mean).collect();
+        let autocorrelation: Vec<f32> = (0..=(COMB_HARMONICS * max_lag).min(count - 1))
+            .map(|lag| envelope.iter().zip(&envelope[lag..]).map(|(a, b)| a * b).sum::<f32>() / (count - lag) as f32)
+            .collect();
+        
+        let energy = autocorrelation[0];
+        if energy <= f32::EPSILON {
+            // Nothing periodic in silence or a steady sound
+            self.confidence = 0.0;
+            return;
+        }
+        
+        // Periods are tried between whole frames too, their multiples drift off the frame grid
+        let interpolated = |lag: f32| {
+            let below = lag.floor() as usize;
+            let fraction = lag - below as f32;
+            Some(autocorrelation.get(below)? * (1.0 - fraction) + autocorrelation.get(below + 1)? * fraction)
+        };
+        let lags: Vec<f32> = (0..=((max_lag - min_lag) as f32 / LAG_STEP) as usize)
+            .map(|step| min_lag as f32 + step as f32 * LAG_STEP)
+            .collect();
+        let scores: Vec<f32> = lags
+            .iter()
+            .map(|&lag| {
+                let harmonics: Vec<f32> = (1..=COMB_HARMONICS)
+                    .filter_map(|multiple| interpolated(multiple as f32 * lag))
+                    .collect();
+                let comb = harmonics.iter().sum::<f32>() / harmonics.len().max(1) as f32;
+                let tempo = 60.0 / (lag * frame_period);
+                let octaves = (tempo / PREFERRED_TEMPO).log2() / TEMPO_PRIOR_OCTAVES;
+                comb * (-0.5 * octaves * octaves).exp()
+            })
+            .collect();
+        let Some(best) = (0..scores.len()).max_by(|&a, &b| scores[a].total_cmp(&scores[b])) else {
+            return;
+        };
+        
+        // Refine the lag between steps with a parabola through the neighbouring scores
+        let offset = match (best.checked_sub(1).map(|index| scores[index]), scores.get(best + 1)) {
+            (Some(before), Some(&after)) => {
+                let curvature = before - 2.0 * scores[best] + after;
+                if curvature < 0.0 { (0.5 * (before - after) / curvature).clamp(-0.5, 0.5) } else { 0.0 }
+            }
+            _ => 0.0,
+        };
+        let lag = lags[best] + offset * LAG_STEP;
+        self.confidence = (interpolated(lag).unwrap_or(0.0) / energy).clamp(0.0, 1.0);
+        if self.confidence < MIN_CONFIDENCE {
+            return;
+        }
+        
+        let measured = 60.0 / (lag * frame_period);
+        if self.tempo <= 0.0 {
+            self.tempo = measured;
+        } else if (measured - self.tempo).abs() <= self.tempo * TEMPO_TOLERANCE {
+            self.tempo += (measured - self.tempo) * (1.0 - (-elapsed / TEMPO_TIME_CONSTANT).exp());
+            self.candidate = None;
+        } else {
+            // A different tempo has to hold for a while, a fill or a break doesn't move the grid
+            let persisted = match self.candidate {
+                Some((candidate, persisted)) if (measured - candidate).abs() <= candidate * TEMPO_TOLERANCE => persisted + elapsed,
+                _ => 0.0,
+            };
+            if persisted >= TEMPO_SWITCH_SECONDS {
+                self.tempo = measured;
+                self.candidate = None;
+            } else {
+                self.candidate = Some((measured, persisted));
+            }
+        }
+    }
+    
+    /// Run the phase on at the tempo, then pull it towards the beats in the envelope
+    fn advance(&mut self, frame_period: f32, elapsed: f32, onset: f32) {
+        if self.tempo <= 0.0 {
+            return;
+        }
+        
+        self.shift_phase(elapsed * self.tempo / 60.0);
+        if self.confidence >= MIN_CONFIDENCE && frame_period > 0.0 {
+            if let Some(measured) = self.measure_phase(frame_period) {
+                let error = (measured - self.phase + 0.5).rem_euclid(1.0) - 0.5;
+                self.shift_phase(error * (1.0 - (-elapsed / PHASE_TIME_CONSTANT).exp()));
+            }
+        }
+        
+        if self.phase < DOWNBEAT_WINDOW {
+            self.beat_energy += onset;
+        }
+    }
+    
+    /// Move the phase by `beats`, counting the beats crossed
+    fn shift_phase(&mut self, beats: f32) {
+        self.phase += beats;
+        while self.phase >= 1.0 {
+            self.phase -= 1.0;
+            self.finish_beat();
+        }
+        while self.phase < 0.0 {
+            // Pulled back over a beat just counted
+            self.phase += 1.0;
+            self.beat_count = self.beat_count.saturating_sub(1);
+        }
+    }
+    
+    /// Phase the envelope puts the current frame at: how far past the strongest recent beat positions it is
+    fn measure_phase(&self, frame_period: f32) -> Option<f32> {
+        let beat_frames = 60.0 / (self.tempo * frame_period);
+        let count = self.envelope.len();
+        if beat_frames < 1.0 || (count as f32) < beat_frames * 2.0 {
+            return None;
+        }
+        
+        let newest = count - 1;
+        let score = |frames_ago: usize| {
+            (0..PHASE_BEATS)
+                .map(|beat| frames_ago + (beat as f32 * beat_frames).round() as usize)
+                .take_while(|&back| back <= newest)
+                .map(|back| self.envelope[newest - back])
+                .sum::<f32>()
+        };
+        let frames_ago = (0..beat_frames.round() as usize).max_by(|&a, &b| score(a).total_cmp(&score(b)))?;
+        (score(frames_ago) > 0.0).then(|| frames_ago as f32 / beat_frames)
+    }
+    
+    /// Credit the finished beat's onsets to its place in the bar, and move the downbeat if another place is clearly stronger
+    fn finish_beat(&mut self) {
+        let place = self.beat_count as usize % BEATS_PER_BAR;
+        self.bar_energy[place] = self.bar_energy[place] * 0.75 + self.beat_energy * 0.25;
+        self.beat_energy = 0.0;
+        self.beat_count += 1;
+        
+        let strongest = (0..BEATS_PER_BAR)
+            .max_by(|&a, &b| self.bar_energy[a].total_cmp(&self.bar_energy[b]))
+            .unwrap_or(0);
+        if self.bar_energy[strongest] > self.bar_energy[self.downbeat] * DOWNBEAT_MARGIN {
+            self.downbeat = strongest;
+        }
+    }
+}
+
+impl Default for BeatTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    
+    /// Frame length of 1024-sample hops at 44.1 kHz (seconds)
+    const FRAME: f32 = 1024.0 / 44100.0;
+    
+    /// Track an envelope of `frame`-long frames with a spike every `beat` seconds (`accent` times as high on every fourth) for `seconds`
+    fn track(frame: f32, beat: f32, accent: f32, seconds: f32) -> Vec<(f32, BeatGrid)> {
+        let start = Instant::now();
+        let mut tracker = BeatTracker::new();
+        let mut last_beat = None;
+        
+        (0..(seconds / frame) as usize)
+            .map(|index| {
+                let time = index as f32 * frame;
+                let beat_index = (time / beat).floor() as i64;
+                let onset = if last_beat != Some(beat_index) {
+                    last_beat = Some(beat_index);
+                    if beat_index % 4 == 0 { accent } else { 1.0 }
+                } else {
+                    0.0
+                };
+                (onset, tracker.update(onset, start + Duration::from_secs_f32(time)))
+            })
+            .collect()
+    }
+    
+    #[test]
+    fn test_tempo_and_phase_lock() {
+        // The same at 43 and 172 frames per second
+        for frame in [FRAME, FRAME / 4.0] {
+            for bpm in [90.0, 120.0, 140.0] {
+                let frames = track(frame, 60.0 / bpm, 1.0, 10.0);
+                let (_, last) = frames.last().unwrap();
+                assert!((last.tempo - bpm).abs() < 1.0, "{} BPM tracked as {}", bpm, last.tempo);
+                assert!(last.tempo_confidence > 0.5);
+            
+                // Once locked, every onset lands on the beat
+                for (onset, grid) in &frames[frames.len() / 2..] {
+                    if *onset > 0.0 {
+                        let off_beat = grid.beat_phase.min(1.0 - grid.beat_phase);
+                        assert!(off_beat < 0.1, "{} BPM onset at beat phase {}", bpm, grid.beat_phase);
+                    }
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn test_bar_phase_finds_the_accent() {
+        let beat = 0.5;
+        let frame = FRAME;
+        let frames = track(frame, beat, 3.0, 12.0);
+        
+        // Accented beats fall every 2 s, starting at 0: the bar restarts there
+        let bar_start = (10.0f32 / frame).ceil() as usize;
+        let bar_phase = frames[bar_start].1.bar_phase;
+        assert!(!(0.1..0.9).contains(&bar_phase), "bar phase {} at the downbeat", bar_phase);
+        let mid_bar = frames[bar_start + (2.0 * beat / frame) as usize].1.bar_phase;
+        assert!((mid_bar - 0.5).abs() < 0.1, "bar phase {} mid-bar", mid_bar);
+    }
+    
+    #[test]
+    fn test_no_tempo_without_onsets() {
+        let frames = track(FRAME, f32::INFINITY, 1.0, 5.0);
+        assert_eq!(frames.last().unwrap().1, BeatGrid::default());
+    }
+}
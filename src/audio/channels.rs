@@ -0,0 +1,135 @@
+/// Gain of the center and surround channels in a stereo downmix (-3 dB)
+const DOWNMIX_GAIN: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Downmix weights for one input channel: (left, right)
+type ChannelWeights = (f32, f32);
+
+/// Stereo downmix weights for a channel count, in the usual WAVE/WASAPI channel order
+///
+/// Front channels map straight through, center and surround channels are mixed
+/// into both sides at -3 dB and the LFE channel is dropped. Unknown layouts send
+/// even channels left and odd channels right.
+fn stereo_weights(channels: usize) -> Vec<ChannelWeights> {
+    const L: ChannelWeights = (1.0, 0.0);
+    const R: ChannelWeights = (0.0, 1.0);
+    const C: ChannelWeights = (DOWNMIX_GAIN, DOWNMIX_GAIN);
+    const LFE: ChannelWeights = (0.0, 0.0);
+    const SL: ChannelWeights = (DOWNMIX_GAIN, 0.0);
+    const SR: ChannelWeights = (0.0, DOWNMIX_GAIN);
+    
+    match channels {
+        1 => vec![(1.0, 1.0)],
+        2 => vec![L, R],
+        // L R C
+        3 => vec![L, R, C],
+        // Quad: L R Ls Rs
+        4 => vec![L, R, SL, SR],
+        // 5.0: L R C Ls Rs
+        5 => vec![L, R, C, SL, SR],
+        // 5.1: L R C LFE Ls Rs
+        6 => vec![L, R, C, LFE, SL, SR],
+        // 6.1: L R C LFE Cs Ls Rs
+        7 => vec![L, R, C, LFE, C, SL, SR],
+        // 7.1: L R C LFE Lrs Rrs Ls Rs
+        8 => vec![L, R, C, LFE, SL, SR, SL, SR],
+        _ => (0..channels).map(|channel| if channel % 2 == 0 { L } else { R }).collect(),
+    }
+}
+
+/// Downmix interleaved multichannel audio to interleaved stereo
+///
+/// Each side is normalized by the sum of its weights, so a full-scale signal
+/// on every channel stays full scale instead of clipping.
+pub fn downmix_to_stereo(samples: &[f32], channels: u16) -> Vec<f32> {
+    let channels = channels.max(1) as usize;
+    if channels == 2 {
+        return samples.to_vec();
+    }
+    
+    let weights = stereo_weights(channels);
+    let left_total: f32 = weights.iter().map(|w| w.0).sum();
+    let right_total: f32 = weights.iter().map(|w| w.1).sum();
+    
+    let mut output = Vec::with_capacity(samples.len() / channels * 2);
+    for frame in samples.chunks_exact(channels) {
+        let (left, right) = frame.iter()
+            .zip(&weights)
+            .fold((0.0, 0.0), |(l, r), (sample, w)| (l + sample * w.0, r + sample * w.1));
+        output.push(left / left_total);
+        output.push(right / right_total);
+    }
+    
+    output
+}
+
+/// Convert interleaved audio between channel counts
+///
+/// Mono is copied to every output channel, anything wider than stereo is
+/// downmixed with the rules of `downmix_to_stereo` first.
+pub fn remix(samples: &[f32], from: u16, to: u16) -> Vec<f32> {
+    let from = from.max(1);
+    let to = to.max(1);
+    if from == to {
+        return samples.to_vec();
+    }
+    
+    if from == 1 {
+        return samples.iter()
+            .flat_map(|&sample| std::iter::repeat_n(sample, to as usize))
+            .collect();
+    }
+    
+    let stereo = downmix_to_stereo(samples, from);
+    match to {
+        1 => stereo.chunks_exact(2).map(|frame| (frame[0] + frame[1]) * 0.5).collect(),
+        2 => stereo,
+        // Wider outputs get the stereo pair on their front channels
+        _ => stereo.chunks_exact(2)
+            .flat_map(|frame| (0..to as usize).map(move |channel| frame.get(channel).copied().unwrap_or(0.0)))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_downmix_5_1() {
+        // Center only lands equally on both sides, LFE is dropped
+        let center = [0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+        let lfe = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+        let left_surround = [0.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        
+        let total = 1.0 + 2.0 * DOWNMIX_GAIN;
+        let output = downmix_to_stereo(&center, 6);
+        assert!((output[0] - DOWNMIX_GAIN / total).abs() < 1e-6);
+        assert_eq!(output[0], output[1]);
+        assert_eq!(downmix_to_stereo(&lfe, 6), vec![0.0, 0.0]);
+        
+        let output = downmix_to_stereo(&left_surround, 6);
+        assert!(output[0] > 0.0);
+        assert_eq!(output[1], 0.0);
+        
+        // Full scale everywhere stays full scale
+        let output = downmix_to_stereo(&[1.0; 6], 6);
+        assert!(output.iter().all(|&sample| (sample - 1.0).abs() < 1e-6));
+    }
+    
+    #[test]
+    fn test_downmix_7_1_keeps_sides_apart() {
+        let frame = [1.0, -1.0, 0.0, 0.0, 1.0, -1.0, 1.0, -1.0];
+        let output = downmix_to_stereo(&frame, 8);
+        assert!(output[0] > 0.5);
+        assert!((output[0] + output[1]).abs() < 1e-6);
+    }
+    
+    #[test]
+    fn test_remix() {
+        assert_eq!(remix(&[0.5, -0.5], 1, 2), vec![0.5, 0.5, -0.5, -0.5]);
+        assert_eq!(remix(&[0.5, -0.5, 1.0, 0.0], 2, 1), vec![0.0, 0.5]);
+        let output = remix(&[0.25; 12], 6, 2);
+        assert_eq!(output.len(), 4);
+        assert!(output.iter().all(|&sample| (sample - 0.25).abs() < 1e-6));
+    }
+}
@@ -0,0 +1,228 @@
+use serde::{Deserialize, Serialize};
+
+/// Frequency of C0, pitch class 0 (Hz)
+const C0: f32 = 16.351598;
+
+/// Highest frequency folded into the chroma (Hz), above it harmonics blur the pitch
+const MAX_FREQUENCY: f32 = 5000.0;
+
+/// Time constant of the chroma smoothing (seconds)
+const CHROMA_TIME_CONSTANT: f32 = 0.1;
+
+/// Time constant of the chroma average keys are estimated from (seconds)
+const KEY_TIME_CONSTANT: f32 = 8.0;
+
+/// Below this total energy a frame counts as silent and leaves the key alone
+const MIN_ENERGY: f32 = 1e-10;
+
+/// Krumhansl-Kessler key profiles, from the tonic up
+const MAJOR_PROFILE: [f32; 12] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+const MINOR_PROFILE: [f32; 12] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+
+/// Major or minor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyMode {
+    #[default]
+    Major,
+    Minor,
+}
+
+/// Pitch content of the audio: chroma, the dominant pitch class and the estimated key
+///
+/// Pitch classes count semitones up from C, so 0 is C, 1 is C# and 9 is A.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Harmony {
+    /// Energy in each pitch class relative to the strongest (0.0 - 1.0), fading out in silence
+    pub chroma: [f32; 12],
+    /// Strongest pitch class right now
+    pub pitch_class: usize,
+    /// Tonic of the estimated key
+    pub key: usize,
+    pub mode: KeyMode,
+    /// How well the last few seconds fit the key's profile (0.0 - 1.0)
+    pub key_confidence: f32,
+}
+
+impl Harmony {
+    /// Hue for the key (0.0 - 1.0), around the circle of fifths
+    ///
+    /// Each of the 24 keys gets its own hue: closely related keys get nearby
+    /// hues, and each minor key sits just past its relative major.
+    pub fn key_hue(&self) -> f32 {
+        let (major, offset) = match self.mode {
+            KeyMode::Major => (self.key, 0.0),
+            KeyMode::Minor => ((self.key + 3) % 12, 0.5),
+        };
+        ((major * 7) % 12) as f32 / 12.0 + offset / 12.0
+    }
+}
+
+/// Where a spectrum bin falls between two neighbouring pitch classes, and its weight in each
+struct BinPitch {
+    bin: usize,
+    classes: [(usize, f32); 2],
+}
+
+/// Folds spectra into 12 pitch classes and estimates the key from them
+///
+/// Each bin's energy is shared between the two pitch classes either side of
+/// its frequency. Bins are used from where they are less than a semitone apart
+/// (about 360 Hz for a 2048-point FFT at 44.1 kHz) up to 5 kHz; lower notes
+/// still register through their harmonics. The key is the major or minor
+/// Krumhansl-Kessler profile that correlates best with the chroma averaged
+/// over the last few seconds.
+pub struct ChromaTracker {
+    bins: Vec<BinPitch>,
+    chroma: [f32; 12],
+    key_chroma: [f32; 12],
+}
+
+impl ChromaTracker {
+    /// Create a tracker for spectra with the given bin frequencies
+    pub fn new(bin_frequencies: &[f32]) -> Self {
+        let spacing = bin_frequencies.get(1).copied().unwrap_or(0.0);
+        let min_frequency = spacing / (2f32.powf(1.0 / 12.0) - 1.0);
+        let bins = bin_frequencies
+            .iter()
+            .enumerate()
+            .filter(|&(_, &frequency)| frequency > 0.0 && frequency >= min_frequency && frequency <= MAX_FREQUENCY)
+            .map(|(bin, &frequency)| {
+                let pitch = 12.0 * (frequency / C0).log2();
+                let nearest = pitch.round();
+                let distance = pitch - nearest;
+                let class = (nearest as i64).rem_euclid(12) as usize;
+                let neighbour = if distance < 0.0 { (class + 11) % 12 } else { (class + 1) % 12 };
+                BinPitch { bin, classes: [(class, 1.0 - distance.abs()), (neighbour, distance.abs())] }
+            })
+            .collect();
+        
+        Self {
+            bins,
+            chroma: [0.0; 12],
+            key_chroma: [0.0; 12],
+        }
+    }
+    
+    /// Feed the magnitude spectrum of a frame covering `duration` seconds of audio
+    pub fn update(&mut self, bins: &[f32], duration: f32) -> Harmony {
+        let mut chroma = [0.0f32; 12];
+        for pitch in &self.bins {
+            let energy = bins.get(pitch.bin).map_or(0.0, |magnitude| magnitude * magnitude);
+            for (class, weight) in pitch.classes {
+                chroma[class] += energy * weight;
+            }
+        }
+        
+        let energy: f32 = chroma.iter().sum();
+        let strongest = chroma.iter().copied().fold(0.0, f32::max);
+        if energy >= MIN_ENERGY {
+            chroma.iter_mut().for_each(|value| *value /= strongest);
+            let gain = 1.0 - (-duration / KEY_TIME_CONSTANT).exp();
+            for (average, value) in self.key_chroma.iter_mut().zip(chroma) {
+                *average += (value - *average) * gain;
+            }
+        } else {
+            chroma = [0.0; 12];
+        }
+        
+        let gain = 1.0 - (-duration / CHROMA_TIME_CONSTANT).exp();
+        for (smoothed, value) in self.chroma.iter_mut().zip(chroma) {
+            *smoothed += (value - *smoothed) * gain;
+        }
+        
+        let pitch_class = (0..12).max_by(|&a, &b| self.chroma[a].total_cmp(&self.chroma[b])).unwrap_or(0);
+        let (key, mode, correlation) = self.estimate_key();
+        Harmony {
+            chroma: self.chroma,
+            pitch_class,
+            key,
+            mode,
+            key_confidence: correlation.clamp(0.0, 1.0),
+        }
+    }
+    
+    /// Key whose profile correlates best with the averaged chroma, and the correlation
+    fn estimate_key(&self) -> (usize, KeyMode, f32) {
+        let mut best = (0, KeyMode::Major, 0.0);
+        for (mode, profile) in [(KeyMode::Major, &MAJOR_PROFILE), (KeyMode::Minor, &MINOR_PROFILE)] {
+            for tonic in 0..12 {
+                let rotated: [f32; 12] = std::array::from_fn(|class| profile[(class + 12 - tonic) % 12]);
+                let correlation = correlation(&self.key_chroma, &rotated);
+                if correlation > best.2 {
+                    best = (tonic, mode, correlation);
+                }
+            }
+        }
+        best
+    }
+}
+
+/// Pearson correlation of two vectors, 0.0 if either is flat
+fn correlation(a: &[f32; 12], b: &[f32; 12]) -> f32 {
+    let mean = |values: &[f32; 12]| values.iter().sum::<f32>() / 12.0;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        covariance += (x - mean_a) * (y - mean_b);
+        variance_a += (x - mean_a) * (x - mean_a);
+        variance_b += (y - mean_b) * (y - mean_b);
+    }
+    if variance_a > 0.0 && variance_b > 0.0 {
+        covariance / (variance_a * variance_b).sqrt()
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Spectrum of a 2048-point FFT at 44.1 kHz with peaks at the bins nearest `frequencies`, the first (the root) loudest
+    fn chord(frequencies: &[f32]) -> Vec<f32> {
+        let mut bins = vec![0.0; 1025];
+        for (index, frequency) in frequencies.iter().enumerate() {
+            bins[(frequency * 2048.0 / 44100.0).round() as usize] = if index == 0 { 0.2 } else { 0.1 };
+        }
+        bins
+    }
+    
+    #[test]
+    fn test_chords_give_their_key() {
+        let bin_frequencies: Vec<f32> = (0..=1024).map(|i| i as f32 * 44100.0 / 2048.0).collect();
+        
+        // C major (C5 E5 G5), then A minor (A5 C6 E6), 10 seconds each at 100 frames a second
+        for (notes, key, mode, root) in [
+            ([523.25, 659.26, 783.99], 0, KeyMode::Major, 0),
+            ([880.0, 1046.5, 1318.5], 9, KeyMode::Minor, 9),
+        ] {
+            let mut tracker = ChromaTracker::new(&bin_frequencies);
+            let spectrum = chord(&notes);
+            let harmony = (0..1000).map(|_| tracker.update(&spectrum, 0.01)).last().unwrap();
+            
+            assert_eq!((harmony.key, harmony.mode), (key, mode), "{:?}", harmony);
+            assert!(harmony.key_confidence > 0.5, "{:?}", harmony);
+            assert_eq!(harmony.pitch_class, root);
+            assert!(harmony.chroma[root] > 0.99, "{:?}", harmony);
+            
+            // Silence fades the chroma but keeps the key
+            let silence = vec![0.0; 1025];
+            let harmony = (0..100).map(|_| tracker.update(&silence, 0.01)).last().unwrap();
+            assert!(harmony.chroma.iter().all(|&value| value < 0.01), "{:?}", harmony);
+            assert_eq!((harmony.key, harmony.mode), (key, mode));
+        }
+        
+        // Every key its own hue
+        let mut hues: Vec<f32> = (0..24)
+            .map(|index| Harmony {
+                key: index % 12,
+                mode: if index < 12 { KeyMode::Major } else { KeyMode::Minor },
+                ..Harmony::default()
+            }.key_hue())
+            .collect();
+        hues.sort_by(f32::total_cmp);
+        hues.dedup();
+        assert_eq!(hues.len(), 24);
+        assert!(hues.iter().all(|&hue| (0.0..1.0).contains(&hue)));
+    }
+}
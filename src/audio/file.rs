@@ -0,0 +1,213 @@
+use anyhow::{Context, Result};
+use rodio::Source;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+use super::source::{AudioFormat, AudioSource, RealtimePacer};
+use super::ring::RingSender;
+use super::AudioFrame;
+
+/// Real-time playback of an audio file (WAV/FLAC/MP3/OGG) as a capture source
+pub struct FileSource {
+    path: PathBuf,
+    buffer_size: usize,
+    play_output: bool,
+    format: Option<AudioFormat>,
+    is_playing: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FileSource {
+    /// Create a source that plays `path` in frames of `buffer_size` samples per channel
+    pub fn new(path: PathBuf, buffer_size: usize, play_output: bool) -> Self {
+        Self {
+            path,
+            buffer_size,
+            play_output,
+            format: None,
+            is_playing: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+    
+    /// Decode the file chunk by chunk, pacing frames at the file's own sample rate
+    fn playback_loop<S: Source>(
+        mut source: S,
+        buffer_size: usize,
+        play_output: bool,
+        sender: RingSender<AudioFrame>,
+        is_playing: Arc<AtomicBool>,
+    ) {
+        let sample_rate = source.sample_rate();
+        let channels = source.channels();
+        let chunk_len = buffer_size.max(1) * channels as usize;
+        
+        // The output stream must live on this thread, it is not Send on every platform
+        let output = if play_output {
+            match rodio::OutputStreamBuilder::open_default_stream() {
+                Ok(mut stream) => {
+                    stream.log_on_drop(false);
+                    let sink = rodio::Sink::connect_new(stream.mixer());
+                    Some((stream, sink))
+                }
+                Err(e) => {
+                    log::warn!("Failed to open output device for file playback: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        
+        let mut pacer = RealtimePacer::new(sample_rate);
+        
+        while is_playing.load(Ordering::Relaxed) {
+            let samples: Vec<f32> = source.by_ref().take(chunk_len).collect();
+            if samples.is_empty() {
+                break;
+            }
+            
+            if let Some((_, sink)) = &output {
+                sink.append(rodio::buffer::SamplesBuffer::new(channels, sample_rate, samples.clone()));
+            }
+            
+            let frame_count = samples.len() / channels as usize;
+            let frame = AudioFrame {
+                samples,
+                timestamp: Instant::now(),
+                sample_rate,
+                channels,
+            };
+            
+            if sender.send(frame).is_err() {
+                // Receiver is gone, nobody is listening anymore
+                break;
+            }
+            
+            // Sleep until the wall clock catches up with the audio we have sent
+            pacer.wait(frame_count);
+        }
+        
+        is_playing.store(false, Ordering::Relaxed);
+    }
+}
+
+impl AudioSource for FileSource {
+    fn start(&mut self, sender: RingSender<AudioFrame>) -> Result<()> {
+        self.stop()?;
+        
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed to open audio file: {}", self.path.display()))?;
+        let decoder = rodio::Decoder::try_from(file)
+            .with_context(|| format!("Failed to decode audio file: {}", self.path.display()))?;
+        
+        let format = AudioFormat {
+            sample_rate: decoder.sample_rate(),
+            channels: decoder.channels(),
+        };
+        log::info!("Playing audio file: {} ({})", self.path.display(), format);
+        
+        self.is_playing.store(true, Ordering::Relaxed);
+        let thread = {
+            let is_playing = Arc::clone(&self.is_playing);
+            let buffer_size = self.buffer_size;
+            let play_output = self.play_output;
+            let path = self.path.clone();
+            std::thread::Builder::new()
+                .name("audio-file-playback".to_string())
+                .spawn(move || {
+                    Self::playback_loop(decoder, buffer_size, play_output, sender, is_playing);
+                    log::info!("Finished playing audio file: {}", path.display());
+                })
+                .context("Failed to spawn file playback thread")?
+        };
+        
+        self.format = Some(format);
+        self.thread = Some(thread);
+        Ok(())
+    }
+    
+    fn stop(&mut self) -> Result<()> {
+        self.is_playing.store(false, Ordering::Relaxed);
+        
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("File playback thread panicked");
+            }
+        }
+        
+        Ok(())
+    }
+    
+    fn describe(&self) -> String {
+        format!("File: {}", self.path.display())
+    }
+    
+    fn format(&self) -> Option<AudioFormat> {
+        self.format
+    }
+}
+
+impl Drop for FileSource {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::ring::ring_channel;
+    
+    /// Write a mono 16-bit PCM WAV file
+    fn write_test_wav(path: &std::path::Path, sample_rate: u32, samples: &[i16]) {
+        let data_len = (samples.len() * 2) as u32;
+        let mut bytes = Vec::with_capacity(44 + data_len as usize);
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        bytes.extend_from_slice(&1u16.to_le_bytes()); // mono
+        bytes.extend_from_slice(&sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        for sample in samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+    
+    #[test]
+    fn test_file_playback_emits_frames() {
+        let path = std::env::temp_dir().join("wcr_viz_file_playback_test.wav");
+        let samples: Vec<i16> = (0..4410).map(|i| ((i % 100) as i16 - 50) * 100).collect();
+        write_test_wav(&path, 44100, &samples);
+        
+        let (sender, receiver) = ring_channel(16);
+        let mut source = FileSource::new(path.clone(), 1024, false);
+        source.start(sender).unwrap();
+        assert_eq!(source.format(), Some(AudioFormat { sample_rate: 44100, channels: 1 }));
+        
+        let mut total = 0;
+        while let Ok(frame) = receiver.recv_timeout(std::time::Duration::from_secs(2)) {
+            assert_eq!(frame.sample_rate, 44100);
+            assert_eq!(frame.channels, 1);
+            total += frame.samples.len();
+            if total >= samples.len() {
+                break;
+            }
+        }
+        
+        source.stop().unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(total, samples.len());
+    }
+}
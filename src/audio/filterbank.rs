@@ -0,0 +1,139 @@
+use crate::config::{AudioConfig, BandScale};
+
+impl BandScale {
+    /// Position of `frequency` (Hz) on the scale
+    fn position(self, frequency: f32) -> f32 {
+        match self {
+            BandScale::Linear => frequency,
+            BandScale::Log => frequency.ln(),
+            BandScale::Mel => 2595.0 * (1.0 + frequency / 700.0).log10(),
+            // Traunmüller's approximation
+            BandScale::Bark => 26.81 * frequency / (1960.0 + frequency) - 0.53,
+        }
+    }
+    
+    /// Frequency (Hz) at a position on the scale
+    fn frequency(self, position: f32) -> f32 {
+        match self {
+            BandScale::Linear => position,
+            BandScale::Log => position.exp(),
+            BandScale::Mel => 700.0 * (10f32.powf(position / 2595.0) - 1.0),
+            BandScale::Bark => 1960.0 * (position + 0.53) / (26.28 - position),
+        }
+    }
+}
+
+/// A single band: the weights of a run of spectrum bins
+struct Band {
+    first_bin: usize,
+    weights: Vec<f32>,
+}
+
+/// Triangular filterbank summarizing the spectrum in a configurable number of bands
+///
+/// Band centres are evenly spaced on the configured scale between the minimum
+/// and maximum frequency, and each band's triangle reaches to its neighbours'
+/// centres. Narrow bands at the bottom of a log scale are widened to at least
+/// one bin either side of their centre, so no band falls between bins and
+/// reads zero.
+pub struct Filterbank {
+    bands: Vec<Band>,
+}
+
+impl Filterbank {
+    /// Create the filterbank configured in `config`, for spectra with the given bin frequencies
+    pub fn new(config: &AudioConfig, bin_frequencies: &[f32]) -> Self {
+        let scale = config.band_scale;
+        let nyquist = bin_frequencies.last().copied().unwrap_or(0.0);
+        let bin_width = bin_frequencies.get(1).copied().unwrap_or(0.0);
+        // At least a bin up, a log scale can't start at 0 Hz
+        let low = scale.position(config.band_min_hz.max(bin_width));
+        let high = scale.position(config.band_max_hz.min(nyquist));
+        
+        // Band edges are the neighbouring centres
+        let step = (high - low) / (config.band_count + 1) as f32;
+        let points: Vec<f32> = (0..config.band_count + 2)
+            .map(|index| scale.frequency(low + step * index as f32))
+            .collect();
+        
+        let bands = points
+            .windows(3)
+            .map(|edges| {
+                let centre = edges[1];
+                let lower = edges[0].min(centre - bin_width);
+                let upper = edges[2].max(centre + bin_width);
+                
+                let first_bin = bin_frequencies.iter().position(|&f| f > lower).unwrap_or(bin_frequencies.len());
+                let weights = bin_frequencies[first_bin..]
+                    .iter()
+                    .take_while(|&&f| f < upper)
+                    .map(|&f| if f < centre { (f - lower) / (centre - lower) } else { (upper - f) / (upper - centre) })
+                    .collect();
+                Band { first_bin, weights }
+            })
+            .collect();
+        
+        Self { bands }
+    }
+    
+    /// Weighted mean magnitude of `bins` in every band, lowest band first
+    pub fn apply(&self, bins: &[f32]) -> Vec<f32> {
+        self.bands
+            .iter()
+            .map(|band| {
+                let bins = bins.get(band.first_bin..).unwrap_or_default();
+                let (sum, weight) = bins
+                    .iter()
+                    .zip(&band.weights)
+                    .fold((0.0, 0.0), |(sum, total), (bin, weight)| (sum + bin * weight, total + weight));
+                if weight > 0.0 { sum / weight } else { 0.0 }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn bin_frequencies(config: &AudioConfig) -> Vec<f32> {
+        (0..=config.fft_size / 2)
+            .map(|i| i as f32 * config.sample_rate as f32 / config.fft_size as f32)
+            .collect()
+    }
+    
+    #[test]
+    fn test_bands_cover_the_spectrum() {
+        for scale in [BandScale::Linear, BandScale::Log, BandScale::Mel, BandScale::Bark] {
+            for band_count in [16, 32, 64] {
+                let config = AudioConfig { band_scale: scale, band_count, ..AudioConfig::default() };
+                let frequencies = bin_frequencies(&config);
+                let filterbank = Filterbank::new(&config, &frequencies);
+                
+                // A flat spectrum is flat in every band, even the narrow ones below a bin apart
+                let bands = filterbank.apply(&vec![1.0; frequencies.len()]);
+                assert_eq!(bands.len(), band_count);
+                assert!(bands.iter().all(|&band| (band - 1.0).abs() < 1e-4), "{:?} {}: {:?}", scale, band_count, bands);
+                
+                // A single tone lands in the band around it, and the bands rise with frequency
+                let peak_band = |bin: usize| {
+                    let mut bins = vec![0.0; frequencies.len()];
+                    bins[bin] = 1.0;
+                    let bands = filterbank.apply(&bins);
+                    (0..bands.len()).max_by(|&a, &b| bands[a].total_cmp(&bands[b])).unwrap()
+                };
+                // About 100 Hz, 2 kHz and 12 kHz
+                let (low, mid, high) = (peak_band(5), peak_band(93), peak_band(558));
+                assert!(low < mid && mid < high, "{:?} {}: {} {} {}", scale, band_count, low, mid, high);
+            }
+        }
+    }
+    
+    #[test]
+    fn test_log_bands_from_zero_hz() {
+        let config = AudioConfig { band_scale: BandScale::Log, band_min_hz: 0.0, ..AudioConfig::default() };
+        let frequencies = bin_frequencies(&config);
+        let bands = Filterbank::new(&config, &frequencies).apply(&vec![1.0; frequencies.len()]);
+        assert!(bands.iter().all(|&band| (band - 1.0).abs() < 1e-4), "{:?}", bands);
+    }
+}
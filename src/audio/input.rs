@@ -1,11 +1,23 @@
 use anyhow::{Context, Result};
-use cpal::traits::{DeviceTrait, HostTrait};
-use cpal::{Device, SampleFormat};
-use log::{info, error};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use log::{info, error, warn};
+use parking_lot::Mutex;
+use std::fmt;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::config::AudioConfig;
 
+/// Samples at or above this magnitude count as clipped
+const CLIP_LEVEL: f32 = 0.999;
+
+/// Share of clipped samples above which an input is reported as clipping
+const CLIPPING_RATIO: f64 = 0.001;
+
+/// How long `test_device` and `get_input_level` capture for
+const QUICK_PROBE_DURATION: Duration = Duration::from_millis(500);
+
 /// Microphone and line input handling
 pub struct AudioInputManager {
     config: AudioConfig,
@@ -122,13 +134,15 @@ impl AudioInputManager {
         );
         
         let can_use_preferred = stream_test.is_ok();
+        drop(stream_test);
         
-        // Get latency information if available
-        let latency = if can_use_preferred {
-            // This is platform-specific and may not be available
-            None
-        } else {
-            None
+        // Measure latency with a short capture in the device's default configuration
+        let latency = match self.probe_device(device, QUICK_PROBE_DURATION) {
+            Ok(probe) => Some(probe.estimated_latency_ms()),
+            Err(e) => {
+                warn!("Failed to probe {}: {:#}", name, e);
+                None
+            }
         };
         
         Ok(DeviceTestResult {
@@ -140,12 +154,88 @@ impl AudioInputManager {
         })
     }
     
-    /// Get input level (for testing microphone)
-    pub fn get_input_level(&self, _device: &Device) -> Result<f32> {
-        // This would require actually capturing some audio
-        // For now, return a placeholder
-        // TODO: Implement actual level detection
-        Ok(0.0)
+    /// Get input level (for testing microphone), as RMS over a short capture
+    pub fn get_input_level(&self, device: &Device) -> Result<f32> {
+        Ok(self.probe_device(device, QUICK_PROBE_DURATION)?.rms)
+    }
+    
+    /// Capture from `device` for `duration` and measure what it delivers
+    ///
+    /// Opens a stream in the device's default configuration and records the
+    /// level, clipping, whether the input is stuck or silent, and the timing of
+    /// the callbacks.
+    pub fn probe_device(&self, device: &Device, duration: Duration) -> Result<DeviceProbe> {
+        let name = device.name().unwrap_or_else(|_| "Unknown".to_string());
+        let default_config = device.default_input_config()
+            .context("Failed to get default input configuration")?;
+        let sample_format = default_config.sample_format();
+        let stream_config: StreamConfig = default_config.into();
+        
+        let meter = Arc::new(Mutex::new(ProbeMeter::new(stream_config.sample_rate.0, stream_config.channels)));
+        let stream = Self::build_probe_stream(device, &stream_config, sample_format, Arc::clone(&meter))?;
+        
+        let started = Instant::now();
+        stream.play().context("Failed to start probe stream")?;
+        std::thread::sleep(duration);
+        drop(stream);
+        
+        let meter = meter.lock();
+        if meter.callbacks == 0 {
+            anyhow::bail!("{} delivered no audio within {:.1} s", name, duration.as_secs_f32());
+        }
+        
+        Ok(meter.finish(name, stream_config, sample_format, started, self.config.silence_threshold_db))
+    }
+    
+    /// Build an input stream in the device's native sample format feeding `meter`
+    fn build_probe_stream(
+        device: &Device,
+        stream_config: &StreamConfig,
+        sample_format: SampleFormat,
+        meter: Arc<Mutex<ProbeMeter>>,
+    ) -> Result<Stream> {
+        let stream = match sample_format {
+            SampleFormat::I8 => Self::build_typed_probe_stream::<i8>(device, stream_config, meter),
+            SampleFormat::I16 => Self::build_typed_probe_stream::<i16>(device, stream_config, meter),
+            SampleFormat::I32 => Self::build_typed_probe_stream::<i32>(device, stream_config, meter),
+            SampleFormat::I64 => Self::build_typed_probe_stream::<i64>(device, stream_config, meter),
+            SampleFormat::U8 => Self::build_typed_probe_stream::<u8>(device, stream_config, meter),
+            SampleFormat::U16 => Self::build_typed_probe_stream::<u16>(device, stream_config, meter),
+            SampleFormat::U32 => Self::build_typed_probe_stream::<u32>(device, stream_config, meter),
+            SampleFormat::U64 => Self::build_typed_probe_stream::<u64>(device, stream_config, meter),
+            SampleFormat::F32 => Self::build_typed_probe_stream::<f32>(device, stream_config, meter),
+            SampleFormat::F64 => Self::build_typed_probe_stream::<f64>(device, stream_config, meter),
+            format => anyhow::bail!("Unsupported sample format: {:?}", format),
+        };
+        
+        stream.with_context(|| format!("Failed to build {:?} probe stream", sample_format))
+    }
+    
+    /// Build a probe stream delivering samples of type `T`
+    fn build_typed_probe_stream<T>(
+        device: &Device,
+        stream_config: &StreamConfig,
+        meter: Arc<Mutex<ProbeMeter>>,
+    ) -> Result<Stream, cpal::BuildStreamError>
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        let error_meter = Arc::clone(&meter);
+        device.build_input_stream(
+            stream_config,
+            move |data: &[T], info: &cpal::InputCallbackInfo| {
+                let timestamp = info.timestamp();
+                let device_latency = timestamp.callback.duration_since(&timestamp.capture);
+                let samples: Vec<f32> = data.iter().map(|&sample| sample.to_sample::<f32>()).collect();
+                meter.lock().record(&samples, Instant::now(), device_latency);
+            },
+            move |err| {
+                error!("Probe stream error: {}", err);
+                error_meter.lock().stream_errors += 1;
+            },
+            None,
+        )
     }
     
     /// Check if a device supports our required configuration
@@ -157,11 +247,11 @@ impl AudioInputManager {
                 let max_rate = config.max_sample_rate().0;
                 
                 if self.config.sample_rate >= min_rate && self.config.sample_rate <= max_rate {
-                    // Check if we support the sample format
+                    // Check if we support the sample format (everything but f32 is converted)
                     match config.sample_format() {
-                        SampleFormat::F32 => return true,
-                        SampleFormat::I16 => return true, // We can convert
-                        SampleFormat::U16 => return true, // We can convert
+                        SampleFormat::F32 | SampleFormat::F64 => return true,
+                        SampleFormat::I8 | SampleFormat::I16 | SampleFormat::I32 | SampleFormat::I64 => return true,
+                        SampleFormat::U8 | SampleFormat::U16 | SampleFormat::U32 | SampleFormat::U64 => return true,
                         _ => continue,
                     }
                 }
@@ -200,6 +290,167 @@ pub struct DeviceTestResult {
     pub last_tested: std::time::Instant,
 }
 
+/// Measurements of a short capture from an input device
+#[derive(Debug, Clone)]
+pub struct DeviceProbe {
+    pub name: String,
+    pub config: StreamConfig,
+    pub sample_format: SampleFormat,
+    
+    /// Audio received, per channel
+    pub captured: Duration,
+    pub callbacks: u64,
+    pub stream_errors: u64,
+    
+    /// RMS and peak level over the whole capture (linear, 1.0 is full scale)
+    pub rms: f32,
+    pub peak: f32,
+    pub clipped_samples: u64,
+    
+    /// Too many samples at full scale, the input gain is too high
+    pub is_clipping: bool,
+    /// Every sample had the same value (muted, disconnected or blocked input)
+    pub is_stuck: bool,
+    /// Level below the silence threshold
+    pub is_silent: bool,
+    
+    /// Time from starting the stream to the first callback
+    pub startup_ms: f32,
+    /// Average audio delivered per callback
+    pub buffer_ms: f32,
+    /// Average delay between capture and callback reported by the driver, if it reports one
+    pub device_latency_ms: Option<f32>,
+}
+
+impl DeviceProbe {
+    /// Delay between sound reaching the converter and the samples reaching us
+    pub fn estimated_latency_ms(&self) -> f32 {
+        self.device_latency_ms.unwrap_or(0.0) + self.buffer_ms
+    }
+}
+
+impl fmt::Display for DeviceProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let db = |level: f32| 20.0 * level.max(1e-10).log10();
+        
+        writeln!(f, "Device:      {}", self.name)?;
+        writeln!(f, "Format:      {} Hz, {} channels, {:?}", self.config.sample_rate.0, self.config.channels, self.sample_format)?;
+        writeln!(f, "Captured:    {:.2} s in {} callbacks ({} stream errors)", self.captured.as_secs_f32(), self.callbacks, self.stream_errors)?;
+        writeln!(f, "Level:       {:.1} dBFS RMS, {:.1} dBFS peak", db(self.rms), db(self.peak))?;
+        writeln!(f, "Clipped:     {} samples", self.clipped_samples)?;
+        writeln!(f, "Startup:     {:.1} ms", self.startup_ms)?;
+        writeln!(f, "Buffer:      {:.1} ms per callback", self.buffer_ms)?;
+        match self.device_latency_ms {
+            Some(latency) => writeln!(f, "Driver:      {:.1} ms capture to callback", latency)?,
+            None => writeln!(f, "Driver:      no capture timestamps")?,
+        }
+        writeln!(f, "Latency:     ~{:.1} ms", self.estimated_latency_ms())?;
+        
+        let status = if self.is_stuck {
+            "STUCK - every sample has the same value, check that the input is unmuted and allowed"
+        } else if self.is_silent {
+            "SILENT - only noise floor, check the cable, source and input gain"
+        } else if self.is_clipping {
+            "CLIPPING - turn the input gain down"
+        } else {
+            "OK"
+        };
+        write!(f, "Status:      {}", status)
+    }
+}
+
+/// Accumulates what a probe stream delivers
+struct ProbeMeter {
+    sample_rate: u32,
+    channels: u16,
+    sum_squares: f64,
+    samples: u64,
+    peak: f32,
+    min: f32,
+    max: f32,
+    clipped: u64,
+    callbacks: u64,
+    stream_errors: u64,
+    first_callback: Option<Instant>,
+    device_latency_sum: Duration,
+    device_latency_count: u64,
+}
+
+impl ProbeMeter {
+    fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            sum_squares: 0.0,
+            samples: 0,
+            peak: 0.0,
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+            clipped: 0,
+            callbacks: 0,
+            stream_errors: 0,
+            first_callback: None,
+            device_latency_sum: Duration::ZERO,
+            device_latency_count: 0,
+        }
+    }
+    
+    /// Account for a callback at `now` delivering `samples`
+    fn record(&mut self, samples: &[f32], now: Instant, device_latency: Option<Duration>) {
+        self.callbacks += 1;
+        self.first_callback.get_or_insert(now);
+        if let Some(latency) = device_latency {
+            self.device_latency_sum += latency;
+            self.device_latency_count += 1;
+        }
+        
+        for &sample in samples {
+            self.sum_squares += (sample * sample) as f64;
+            self.peak = self.peak.max(sample.abs());
+            self.min = self.min.min(sample);
+            self.max = self.max.max(sample);
+            if sample.abs() >= CLIP_LEVEL {
+                self.clipped += 1;
+            }
+        }
+        self.samples += samples.len() as u64;
+    }
+    
+    fn finish(
+        &self,
+        name: String,
+        config: StreamConfig,
+        sample_format: SampleFormat,
+        started: Instant,
+        silence_threshold_db: f32,
+    ) -> DeviceProbe {
+        let rms = if self.samples > 0 { (self.sum_squares / self.samples as f64).sqrt() as f32 } else { 0.0 };
+        let frames = self.samples as f64 / self.channels.max(1) as f64;
+        let captured = Duration::from_secs_f64(frames / self.sample_rate.max(1) as f64);
+        
+        DeviceProbe {
+            name,
+            config,
+            sample_format,
+            captured,
+            callbacks: self.callbacks,
+            stream_errors: self.stream_errors,
+            rms,
+            peak: self.peak,
+            clipped_samples: self.clipped,
+            is_clipping: self.clipped as f64 > self.samples as f64 * CLIPPING_RATIO,
+            is_stuck: self.samples == 0 || self.max - self.min <= f32::EPSILON,
+            is_silent: 20.0 * rms.max(1e-10).log10() < silence_threshold_db,
+            startup_ms: self.first_callback
+                .map_or(0.0, |first| first.saturating_duration_since(started).as_secs_f32() * 1000.0),
+            buffer_ms: captured.as_secs_f32() * 1000.0 / self.callbacks.max(1) as f32,
+            device_latency_ms: (self.device_latency_count > 0).then(|| {
+                self.device_latency_sum.as_secs_f32() * 1000.0 / self.device_latency_count as f32
+            }),
+        }
+    }
+}
+
 /// Input device monitoring for level detection
 pub struct InputMonitor {
     device: Device,
@@ -270,6 +521,7 @@ mod tests {
             capture_mode: AudioCaptureMode::Input,
             enable_loopback: false,
             target_latency_ms: 50.0,
+            ..AudioConfig::default()
         };
         
         let input_manager = AudioInputManager::new(&config);
@@ -286,6 +538,7 @@ mod tests {
             capture_mode: AudioCaptureMode::Input,
             enable_loopback: false,
             target_latency_ms: 50.0,
+            ..AudioConfig::default()
         };
         
         let input_manager = AudioInputManager::new(&config).unwrap();
@@ -294,4 +547,45 @@ mod tests {
         let devices = input_manager.list_input_devices();
         assert!(devices.is_ok());
     }
+    
+    #[test]
+    fn test_probe_meter() {
+        let start = Instant::now();
+        let config = StreamConfig {
+            channels: 2,
+            sample_rate: cpal::SampleRate(48000),
+            buffer_size: cpal::BufferSize::Default,
+        };
+        let probe = |samples: &[f32], latency: Option<Duration>| {
+            let mut meter = ProbeMeter::new(48000, 2);
+            for callback in 0..10 {
+                let now = start + Duration::from_millis(5 + callback * 10);
+                meter.record(samples, now, latency);
+            }
+            meter.finish("Test".to_string(), config.clone(), SampleFormat::F32, start, -60.0)
+        };
+        
+        // 480 stereo frames per callback are 10 ms buffers
+        let tone: Vec<f32> = (0..960).map(|n| if n % 4 < 2 { 0.5 } else { -0.5 }).collect();
+        let result = probe(&tone, Some(Duration::from_millis(3)));
+        assert!((result.rms - 0.5).abs() < 1e-6);
+        assert_eq!(result.peak, 0.5);
+        assert!(!result.is_clipping && !result.is_stuck && !result.is_silent);
+        assert!((result.captured.as_secs_f32() - 0.1).abs() < 1e-6);
+        assert!((result.startup_ms - 5.0).abs() < 1e-3);
+        assert!((result.buffer_ms - 10.0).abs() < 1e-3);
+        assert!((result.estimated_latency_ms() - 13.0).abs() < 1e-3);
+        
+        let clipped: Vec<f32> = tone.iter().map(|sample| sample * 2.5).collect();
+        let result = probe(&clipped, None);
+        assert!(result.is_clipping);
+        assert_eq!(result.device_latency_ms, None);
+        
+        // Digital silence is stuck, a noise floor is only silent
+        let result = probe(&[0.0; 960], None);
+        assert!(result.is_stuck && result.is_silent);
+        let noise: Vec<f32> = tone.iter().map(|sample| sample * 1e-4).collect();
+        let result = probe(&noise, None);
+        assert!(!result.is_stuck && result.is_silent);
+    }
 }
\ No newline at end of file
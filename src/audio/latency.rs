@@ -0,0 +1,276 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::levels::MilkdropLevels;
+use super::{AudioData, AudioFeatures};
+
+/// Largest A/V offset that can be dialed in, either way (milliseconds)
+const MAX_OFFSET_MS: f32 = 1000.0;
+
+/// Furthest features are extrapolated when data arrives too late to be delayed
+const MAX_PREDICTION: Duration = Duration::from_millis(100);
+
+/// Smoothing factor of the measured pipeline latency
+const LATENCY_SMOOTHING: f32 = 0.05;
+
+/// Lines analyzed audio up with what is being heard
+///
+/// Audio data is shown `offset` after its capture time (`AudioData::timestamp`,
+/// which device sources backdate to when the audio hit the converter). Data
+/// arriving early is held back. Data arriving late, because the offset is
+/// smaller than the capture and analysis latency, is shown right away with its
+/// levels extrapolated by the time it is late, so beats still land on time.
+pub struct LatencyCompensator {
+    offset_ms: f32,
+    pending: VecDeque<AudioData>,
+    /// Last released data before extrapolation, used for the level trend
+    previous: Option<AudioData>,
+    /// Smoothed delay between capture and arrival (milliseconds)
+    measured_latency_ms: Option<f32>,
+}
+
+impl LatencyCompensator {
+    /// Create a compensator showing audio `offset_ms` after it was captured
+    pub fn new(offset_ms: f32) -> Self {
+        Self {
+            offset_ms: offset_ms.clamp(-MAX_OFFSET_MS, MAX_OFFSET_MS),
+            pending: VecDeque::new(),
+            previous: None,
+            measured_latency_ms: None,
+        }
+    }
+    
+    /// Move the offset by `delta_ms`, returning the new offset
+    pub fn nudge(&mut self, delta_ms: f32) -> f32 {
+        self.offset_ms = (self.offset_ms + delta_ms).clamp(-MAX_OFFSET_MS, MAX_OFFSET_MS);
+        self.offset_ms
+    }
+    
+    /// Average delay between capture and arrival of audio data (milliseconds)
+    pub fn measured_latency_ms(&self) -> Option<f32> {
+        self.measured_latency_ms
+    }
+    
+    /// Queue newly analyzed data that arrived at `now`
+    pub fn push(&mut self, data: AudioData, now: Instant) {
+        let latency_ms = now.saturating_duration_since(data.timestamp).as_secs_f32() * 1000.0;
+        self.measured_latency_ms = Some(match self.measured_latency_ms {
+            Some(average) => average + (latency_ms - average) * LATENCY_SMOOTHING,
+            None => latency_ms,
+        });
+        
+        self.pending.push_back(data);
+    }
+    
+    /// Newest data due to be shown at `now`, if any became due since the last call
+    ///
+    /// Older due data is skipped, but its one-hop pulses (onsets, beat
+    /// confidence and onset strength) are carried into the data returned.
+    pub fn poll(&mut self, now: Instant) -> Option<AudioData> {
+        let mut due: Option<AudioData> = None;
+        while let Some(data) = self.pending.front() {
+            if self.display_time(data) > now {
+                break;
+            }
+            let mut data = self.pending.pop_front()?;
+            if let Some(skipped) = &due {
+                let (skipped, features) = (&skipped.features, &mut data.features);
+                features.onsets = features.onsets.union(skipped.onsets);
+                features.beat_confidence = features.beat_confidence.max(skipped.beat_confidence);
+                features.onset_strength = features.onset_strength.max(skipped.onset_strength);
+            }
+            due = Some(data);
+        }
+        
+        let data = due?;
+        let late = now.saturating_duration_since(self.display_time(&data)).min(MAX_PREDICTION);
+        let shown = match &self.previous {
+            Some(previous) if !late.is_zero() => Self::extrapolate(previous, &data, late),
+            _ => data.clone(),
+        };
+        
+        self.previous = Some(data);
+        Some(shown)
+    }
+    
+    /// Wall clock time at which `data` should be on screen
+    fn display_time(&self, data: &AudioData) -> Instant {
+        let offset = Duration::from_micros((self.offset_ms.abs() * 1000.0).round() as u64);
+        if self.offset_ms >= 0.0 {
+            data.timestamp + offset
+        } else {
+            data.timestamp.checked_sub(offset).unwrap_or(data.timestamp)
+        }
+    }
+    
+    /// Continue the level trend from `previous` to `current` for `ahead`
+    fn extrapolate(previous: &AudioData, current: &AudioData, ahead: Duration) -> AudioData {
+        let interval = current.timestamp.saturating_duration_since(previous.timestamp).as_secs_f32();
+        if interval <= 0.0 {
+            return current.clone();
+        }
+        
+        let ratio = ahead.as_secs_f32() / interval;
+        let predict = |previous: f32, current: f32| (current + (current - previous) * ratio).max(0.0);
+        
+        let (a, b) = (&previous.features, &current.features);
+        let (m, n) = (&a.milkdrop, &b.milkdrop);
+        let beats = ahead.as_secs_f32() * b.tempo / 60.0;
+        let mut data = current.clone();
+        data.features = AudioFeatures {
+            volume: predict(a.volume, b.volume),
+            peak: predict(a.peak, b.peak),
+            sub_bass: predict(a.sub_bass, b.sub_bass),
+            bass: predict(a.bass, b.bass),
+            low_mid: predict(a.low_mid, b.low_mid),
+            mid: predict(a.mid, b.mid),
+            high_mid: predict(a.high_mid, b.high_mid),
+            presence: predict(a.presence, b.presence),
+            brilliance: predict(a.brilliance, b.brilliance),
+            // The levels presets read
+            milkdrop: MilkdropLevels {
+                bass: predict(m.bass, n.bass),
+                mid: predict(m.mid, n.mid),
+                treb: predict(m.treb, n.treb),
+                vol: predict(m.vol, n.vol),
+                bass_att: predict(m.bass_att, n.bass_att),
+                mid_att: predict(m.mid_att, n.mid_att),
+                treb_att: predict(m.treb_att, n.treb_att),
+                vol_att: predict(m.vol_att, n.vol_att),
+            },
+            // The beat grid runs on at its tempo
+            beat_phase: (b.beat_phase + beats).fract(),
+            bar_phase: (b.bar_phase + beats / 4.0).fract(),
+            ..b.clone()
+        };
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::audio::chroma::Harmony;
+    use crate::audio::onset::BandOnsets;
+    use crate::audio::{AudioQueueStats, ChannelData, FrequencyData, StereoFeatures};
+    
+    /// Data captured at `timestamp` with `bass` as both its bass and MilkDrop bass level
+    fn test_data(timestamp: Instant, bass: f32) -> AudioData {
+        let spectrum = FrequencyData {
+            bins: Vec::new(),
+            bin_frequencies: Arc::default(),
+            peak_frequency: 0.0,
+            spectral_centroid: 0.0,
+            spectral_energy: 0.0,
+        };
+        let channel = ChannelData { waveform: Vec::new(), spectrum: spectrum.clone() };
+        
+        AudioData {
+            waveform: Vec::new(),
+            spectrum,
+            bands: Vec::new(),
+            features: AudioFeatures {
+                volume: 0.0,
+                peak: 0.0,
+                sub_bass: 0.0,
+                bass,
+                low_mid: 0.0,
+                mid: 0.0,
+                high_mid: 0.0,
+                presence: 0.0,
+                brilliance: 0.0,
+                beat_confidence: 0.0,
+                onset_strength: 0.0,
+                onsets: BandOnsets::default(),
+                tempo: 0.0,
+                tempo_confidence: 0.0,
+                beat_phase: 0.0,
+                bar_phase: 0.0,
+                milkdrop: MilkdropLevels { bass, bass_att: bass, ..MilkdropLevels::default() },
+                harmony: Harmony::default(),
+                zero_crossing_rate: 0.0,
+                spectral_centroid: 0.0,
+                spectral_rolloff: 0.0,
+            },
+            left: channel.clone(),
+            right: channel,
+            stereo: StereoFeatures::default(),
+            timestamp,
+            queue_stats: AudioQueueStats::default(),
+        }
+    }
+    
+    #[test]
+    fn test_data_is_delayed_by_offset() {
+        let start = Instant::now();
+        let ms = |ms: u64| start + Duration::from_millis(ms);
+        let mut compensator = LatencyCompensator::new(50.0);
+        
+        compensator.push(test_data(ms(0), 0.1), ms(20));
+        compensator.push(test_data(ms(10), 0.2), ms(30));
+        assert_eq!(compensator.measured_latency_ms().map(|ms| ms.round()), Some(20.0));
+        
+        // Nothing is due before capture time + offset
+        assert!(compensator.poll(ms(40)).is_none());
+        assert_eq!(compensator.poll(ms(50)).unwrap().features.bass, 0.1);
+        assert!(compensator.poll(ms(55)).is_none());
+        
+        // Falling behind skips straight to the newest due data
+        compensator.push(test_data(ms(20), 0.3), ms(40));
+        let shown = compensator.poll(ms(70)).unwrap();
+        assert_eq!(shown.timestamp, ms(20));
+        assert!(compensator.poll(ms(80)).is_none());
+    }
+    
+    #[test]
+    fn test_skipped_data_keeps_its_pulses() {
+        let start = Instant::now();
+        let ms = |ms: u64| start + Duration::from_millis(ms);
+        let mut compensator = LatencyCompensator::new(0.0);
+        
+        // Three hops due at once, only the middle one with an onset
+        let mut onset = test_data(ms(6), 0.2);
+        onset.features.onsets.bass = true;
+        onset.features.beat_confidence = 0.8;
+        onset.features.onset_strength = 0.6;
+        compensator.push(test_data(ms(0), 0.1), ms(0));
+        compensator.push(onset, ms(6));
+        compensator.push(test_data(ms(12), 0.3), ms(12));
+        
+        let shown = compensator.poll(ms(12)).unwrap();
+        assert_eq!(shown.timestamp, ms(12));
+        assert_eq!(shown.features.bass, 0.3);
+        assert!(shown.features.onsets.bass && !shown.features.onsets.mid);
+        assert_eq!(shown.features.beat_confidence, 0.8);
+        assert_eq!(shown.features.onset_strength, 0.6);
+        
+        // Pulses are shown once
+        compensator.push(test_data(ms(18), 0.3), ms(18));
+        assert!(!compensator.poll(ms(18)).unwrap().features.onsets.bass);
+    }
+    
+    #[test]
+    fn test_late_data_is_extrapolated() {
+        let start = Instant::now();
+        let ms = |ms: u64| start + Duration::from_millis(ms);
+        let mut compensator = LatencyCompensator::new(0.0);
+        assert_eq!(compensator.nudge(-10.0), -10.0);
+        
+        // Rising by 0.1 every 10 ms, shown 10 ms ahead of capture
+        compensator.push(test_data(ms(0), 0.1), ms(0));
+        assert!((compensator.poll(ms(0)).unwrap().features.bass - 0.1).abs() < 1e-6);
+        compensator.push(test_data(ms(10), 0.2), ms(10));
+        let shown = compensator.poll(ms(10)).unwrap();
+        assert!((shown.features.bass - 0.3).abs() < 1e-4);
+        
+        // Presets see the predicted levels too
+        assert!((shown.features.milkdrop.bass - 0.3).abs() < 1e-4);
+        assert!((shown.features.milkdrop.bass_att - 0.3).abs() < 1e-4);
+        assert_eq!(shown.features.milkdrop.mid, 0.0);
+        
+        // Falling levels never go negative
+        compensator.push(test_data(ms(20), 0.0), ms(20));
+        assert_eq!(compensator.poll(ms(20)).unwrap().features.bass, 0.0);
+    }
+}
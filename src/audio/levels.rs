@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+/// Frame rate MilkDrop's smoothing rates are given for
+const REFERENCE_FPS: f32 = 30.0;
+
+/// Short-term average, per reference frame: faster while the level rises than while it falls
+const ATTACK_RATE: f32 = 0.2;
+const DECAY_RATE: f32 = 0.5;
+
+/// Long-term average, per reference frame, and the quicker rate it settles with at first
+const LONG_TERM_RATE: f32 = 0.992;
+const WARMUP_RATE: f32 = 0.9;
+
+/// Audio the long-term average settles over at the warmup rate (MilkDrop's first 50 frames)
+const WARMUP_SECONDS: f32 = 50.0 / REFERENCE_FPS;
+
+/// Below this long-term average a band counts as silent and reads 1.0 (MilkDrop's
+/// 0.001, rescaled from its 16-bit unnormalized FFT to our normalized magnitudes)
+const MIN_LONG_TERM: f32 = 1e-6;
+
+/// Bass, mid and treble levels as MilkDrop presets see them
+///
+/// Each is relative to its long-term average for the song, so 1.0 is
+/// "normal", 2.0 is twice as loud as usual, and presets react the same to
+/// quiet and loud music. The `_att` levels are attenuated: smoothed with
+/// MilkDrop's attack/decay before being taken relative to the same average.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct MilkdropLevels {
+    pub bass: f32,
+    pub mid: f32,
+    pub treb: f32,
+    pub vol: f32,
+    pub bass_att: f32,
+    pub mid_att: f32,
+    pub treb_att: f32,
+    pub vol_att: f32,
+}
+
+/// Follows the bass, mid and treble levels the way MilkDrop does
+///
+/// Bands are the bottom half of the spectrum split into thirds, weighted
+/// with MilkDrop's equalization, which lifts the higher bins.
+pub struct LevelTracker {
+    /// Weight of every bin in each band
+    band_weights: [Vec<f32>; 3],
+    average: [f32; 3],
+    long_term: [f32; 3],
+    /// Audio seen so far (seconds), for the warmup
+    elapsed: f32,
+}
+
+impl LevelTracker {
+    /// Create a tracker for spectra with the given bin frequencies, of audio at `sample_rate`
+    pub fn new(bin_frequencies: &[f32], sample_rate: f32) -> Self {
+        let nyquist = sample_rate / 2.0;
+        let band_weights = std::array::from_fn(|band| {
+            let (low, high) = (nyquist * band as f32 / 6.0, nyquist * (band + 1) as f32 / 6.0);
+            bin_frequencies
+                .iter()
+                .map(|&frequency| {
+                    if frequency >= low && frequency < high {
+                        -(1.0 - frequency / nyquist).ln()
+                    } else {
+                        0.0
+                    }
+                })
+                .collect()
+        });
+        
+        Self {
+            band_weights,
+            average: [0.0; 3],
+            long_term: [0.0; 3],
+            elapsed: 0.0,
+        }
+    }
+    
+    /// Feed the magnitude spectrum of a frame covering `duration` seconds of audio
+    pub fn update(&mut self, bins: &[f32], duration: f32) -> MilkdropLevels {
+        // MilkDrop's rates are per frame at 30 fps, scale them to this frame's length
+        let frames = duration * REFERENCE_FPS;
+        let warming_up = self.elapsed < WARMUP_SECONDS;
+        self.elapsed += duration;
+        
+        let mut relative = [1.0; 3];
+        let mut relative_att = [1.0; 3];
+        for band in 0..3 {
+            let level: f32 = bins.iter().zip(&self.band_weights[band]).map(|(bin, weight)| bin * weight).sum();
+            
+            let rate = (if level > self.average[band] { ATTACK_RATE } else { DECAY_RATE }).powf(frames);
+            self.average[band] = self.average[band] * rate + level * (1.0 - rate);
+            
+            let rate = (if warming_up { WARMUP_RATE } else { LONG_TERM_RATE }).powf(frames);
+            self.long_term[band] = self.long_term[band] * rate + level * (1.0 - rate);
+            
+            if self.long_term[band].abs() >= MIN_LONG_TERM {
+                relative[band] = level / self.long_term[band];
+                relative_att[band] = self.average[band] / self.long_term[band];
+            }
+        }
+        
+        let [bass, mid, treb] = relative;
+        let [bass_att, mid_att, treb_att] = relative_att;
+        MilkdropLevels {
+            bass,
+            mid,
+            treb,
+            vol: (bass + mid + treb) / 3.0,
+            bass_att,
+            mid_att,
+            treb_att,
+            vol_att: (bass_att + mid_att + treb_att) / 3.0,
+        }
+    }
+}
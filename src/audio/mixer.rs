@@ -0,0 +1,497 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::{Select, TryRecvError};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use super::channels::remix;
+use super::resample::Resampler;
+use super::ring::{ring_channel, RingReceiver, RingSender};
+use super::source::{AudioFormat, AudioSource};
+use super::AudioFrame;
+
+/// How many buffers a source may run ahead before lagging sources are padded with silence
+const MAX_LAG_BUFFERS: usize = 4;
+
+/// Frames each source may queue before its oldest frames are dropped
+const LANE_QUEUE_SIZE: usize = 64;
+
+/// How long the mixer thread waits for frames before checking whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Largest timestamp difference between sources that is aligned, beyond it their clocks can't be compared
+const MAX_ALIGNMENT: Duration = Duration::from_secs(1);
+
+/// Audio source mixing several sources into one stream
+///
+/// Every source is converted to the mixer's format, buffered and summed with
+/// its own gain, so e.g. a microphone and the system audio can drive the
+/// visuals together. Sources are aligned on their capture timestamps: one
+/// that starts later is padded with silence, and samples from before the
+/// mixer's clock are dropped. A source that stalls is padded with silence once
+/// the others are `MAX_LAG_BUFFERS` buffers ahead, which also bounds clock
+/// drift between devices.
+pub struct MixerSource {
+    sources: Vec<(Box<dyn AudioSource>, f32)>,
+    /// Index of the source that follows device switches, the others keep their devices
+    device_source: Option<usize>,
+    format: AudioFormat,
+    buffer_size: usize,
+    is_running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Per-source state on the mixer thread
+struct MixerLane {
+    receiver: RingReceiver<AudioFrame>,
+    gain: f32,
+    resampler: Option<Resampler>,
+    /// Samples converted to the mixer's format, waiting to be mixed
+    queue: VecDeque<f32>,
+    /// Capture time of the first sample in `queue`
+    front_timestamp: Option<Instant>,
+    connected: bool,
+}
+
+impl MixerLane {
+    /// Convert a frame to the mixer's format and queue it
+    fn push(&mut self, frame: AudioFrame, format: AudioFormat) {
+        let samples = remix(&frame.samples, frame.channels, format.channels);
+        
+        let resampler = match &mut self.resampler {
+            Some(resampler) if resampler.input_rate() == frame.sample_rate => resampler,
+            resampler => resampler.insert(Resampler::new(frame.sample_rate, format.sample_rate, format.channels)),
+        };
+        let samples = resampler.process(&samples);
+        
+        if self.queue.is_empty() {
+            self.front_timestamp = Some(frame.timestamp);
+        }
+        self.queue.extend(samples);
+    }
+    
+    /// Drop or pad leading samples so the queue starts at `time`, to within a sample period
+    fn align(&mut self, time: Instant, format: AudioFormat) {
+        let Some(front) = self.front_timestamp else {
+            return;
+        };
+        let channels = format.channels.max(1) as usize;
+        let frames = |gap: Duration| (gap.as_secs_f64() * format.sample_rate as f64).round() as usize;
+        
+        if front > time && front - time <= MAX_ALIGNMENT {
+            // Starts late: silence until its first sample
+            let padding = frames(front - time) * channels;
+            for _ in 0..padding {
+                self.queue.push_front(0.0);
+            }
+        } else if front < time && time - front <= MAX_ALIGNMENT {
+            // Starts early: these samples belong to audio that was already mixed
+            let late = (frames(time - front) * channels).min(self.queue.len());
+            self.queue.drain(..late);
+        }
+        self.front_timestamp = if self.queue.is_empty() { None } else { Some(time) };
+    }
+    
+    /// Remove up to `output.len()` samples and add them to `output` with this lane's gain
+    fn pop_into(&mut self, output: &mut [f32], format: AudioFormat) {
+        let len = output.len().min(self.queue.len());
+        for (out, sample) in output.iter_mut().zip(self.queue.drain(..len)) {
+            *out += sample * self.gain;
+        }
+        
+        let frames = len / format.channels.max(1) as usize;
+        self.front_timestamp = if self.queue.is_empty() {
+            None
+        } else {
+            self.front_timestamp
+                .map(|t| t + Duration::from_secs_f64(frames as f64 / format.sample_rate as f64))
+        };
+    }
+}
+
+impl MixerSource {
+    /// Create an empty mixer producing `format` in frames of `buffer_size` samples per channel
+    pub fn new(format: AudioFormat, buffer_size: usize) -> Self {
+        Self {
+            sources: Vec::new(),
+            device_source: None,
+            format,
+            buffer_size: buffer_size.max(1),
+            is_running: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+    
+    /// Add a source mixed in with the given gain
+    pub fn with_source(mut self, source: Box<dyn AudioSource>, gain: f32) -> Self {
+        self.sources.push((source, gain));
+        self
+    }
+    
+    /// Add a source mixed in with the given gain, which switches device with `with_device`
+    pub fn with_device_source(mut self, source: Box<dyn AudioSource>, gain: f32) -> Self {
+        self.device_source = Some(self.sources.len());
+        self.with_source(source, gain)
+    }
+    
+    /// Mixer loop, runs until stopped, the receiver goes away or all sources have ended
+    fn mix_loop(
+        mut lanes: Vec<MixerLane>,
+        format: AudioFormat,
+        buffer_size: usize,
+        sender: RingSender<AudioFrame>,
+        is_running: Arc<AtomicBool>,
+    ) {
+        let chunk_len = buffer_size * format.channels.max(1) as usize;
+        // Capture time of the next sample to mix, the clock every source is aligned to
+        let mut clock = None;
+        
+        while is_running.load(Ordering::Relaxed) {
+            // Drain everything available so one busy source can't starve the others
+            for lane in lanes.iter_mut().filter(|lane| lane.connected) {
+                loop {
+                    match lane.receiver.try_recv() {
+                        Ok(frame) => lane.push(frame, format),
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => {
+                            lane.connected = false;
+                            break;
+                        }
+                    }
+                }
+            }
+            
+            let all_ended = lanes.iter().all(|lane| !lane.connected);
+            while let Some(frame) = Self::mix_chunk(&mut lanes, &mut clock, format, chunk_len, all_ended) {
+                if sender.send(frame).is_err() {
+                    return;
+                }
+            }
+            
+            if all_ended {
+                break;
+            }
+            
+            // Wait for any source to deliver more frames
+            let connected: Vec<&MixerLane> = lanes.iter().filter(|lane| lane.connected).collect();
+            let mut select = Select::new();
+            for lane in &connected {
+                select.recv(&lane.receiver);
+            }
+            let _ = select.ready_timeout(POLL_INTERVAL);
+        }
+    }
+    
+    /// Mix one chunk if every source has enough data, or if lagging sources have to be padded
+    fn mix_chunk(
+        lanes: &mut [MixerLane],
+        clock: &mut Option<Instant>,
+        format: AudioFormat,
+        chunk_len: usize,
+        flush: bool,
+    ) -> Option<AudioFrame> {
+        // Line the sources up on the clock, starting it at the earliest queued sample
+        if clock.is_none() {
+            *clock = lanes.iter().filter_map(|lane| lane.front_timestamp).min();
+        }
+        if let Some(time) = *clock {
+            for lane in lanes.iter_mut() {
+                lane.align(time, format);
+            }
+        }
+        
+        let longest = lanes.iter().map(|lane| lane.queue.len()).max().unwrap_or(0);
+        if longest == 0 || (longest < chunk_len && !flush) {
+            return None;
+        }
+        
+        // Wait for connected sources that are behind, unless they fell too far behind
+        let waiting = lanes.iter().any(|lane| lane.connected && lane.queue.len() < chunk_len);
+        if waiting && longest < MAX_LAG_BUFFERS * chunk_len {
+            return None;
+        }
+        
+        let timestamp = clock.unwrap_or_else(Instant::now);
+        
+        let mut samples = vec![0.0; chunk_len];
+        for lane in lanes.iter_mut() {
+            lane.pop_into(&mut samples, format);
+        }
+        
+        // Restart the clock from the next frames once every source has run dry, so a gap doesn't add latency
+        let frames = chunk_len / format.channels.max(1) as usize;
+        *clock = if lanes.iter().all(|lane| lane.queue.is_empty()) {
+            None
+        } else {
+            Some(timestamp + Duration::from_secs_f64(frames as f64 / format.sample_rate as f64))
+        };
+        
+        Some(AudioFrame {
+            samples,
+            timestamp,
+            sample_rate: format.sample_rate,
+            channels: format.channels,
+        })
+    }
+}
+
+impl AudioSource for MixerSource {
+    fn start(&mut self, sender: RingSender<AudioFrame>) -> Result<()> {
+        self.stop()?;
+        
+        let mut lanes = Vec::new();
+        for (source, gain) in self.sources.iter_mut() {
+            let (lane_sender, receiver) = ring_channel(LANE_QUEUE_SIZE);
+            match source.start(lane_sender) {
+                Ok(()) => {
+                    log::info!("Mixing {} (gain {:.2})", source.describe(), gain);
+                    lanes.push(MixerLane {
+                        receiver,
+                        gain: *gain,
+                        resampler: None,
+                        queue: VecDeque::new(),
+                        front_timestamp: None,
+                        connected: true,
+                    });
+                }
+                Err(e) => log::warn!("Failed to start {}: {:#}", source.describe(), e),
+            }
+        }
+        
+        if lanes.is_empty() {
+            anyhow::bail!("None of the mixer's sources could be started");
+        }
+        
+        self.is_running.store(true, Ordering::Relaxed);
+        let thread = {
+            let format = self.format;
+            let buffer_size = self.buffer_size;
+            let is_running = Arc::clone(&self.is_running);
+            std::thread::Builder::new()
+                .name("audio-mixer".to_string())
+                .spawn(move || {
+                    Self::mix_loop(lanes, format, buffer_size, sender, is_running);
+                })
+                .context("Failed to spawn audio mixer thread")?
+        };
+        
+        self.thread = Some(thread);
+        Ok(())
+    }
+    
+    fn stop(&mut self) -> Result<()> {
+        for (source, _) in self.sources.iter_mut() {
+            source.stop()?;
+        }
+        
+        self.is_running.store(false, Ordering::Relaxed);
+        
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Audio mixer thread panicked");
+            }
+        }
+        
+        Ok(())
+    }
+    
+    fn describe(&self) -> String {
+        let sources: Vec<String> = self.sources.iter()
+            .map(|(source, gain)| format!("{} x{:.2}", source.describe(), gain))
+            .collect();
+        format!("Mix: {}", sources.join(" + "))
+    }
+    
+    fn format(&self) -> Option<AudioFormat> {
+        Some(self.format)
+    }
+    
+    fn is_healthy(&self) -> bool {
+        self.sources.iter().all(|(source, _)| source.is_healthy())
+    }
+    
+    fn with_device(&self, device_name: Option<String>) -> Result<Box<dyn AudioSource>> {
+        if self.device_source.is_none() {
+            anyhow::bail!("{} has no source that switches devices", self.describe());
+        }
+        
+        let mut mixer = MixerSource::new(self.format, self.buffer_size);
+        for (index, (source, gain)) in self.sources.iter().enumerate() {
+            // The other sources are rebuilt on the devices they already use
+            mixer = if self.device_source == Some(index) {
+                mixer.with_device_source(source.with_device(device_name.clone())?, *gain)
+            } else {
+                mixer.with_source(source.with_device(source.device_name())?, *gain)
+            };
+        }
+        Ok(Box::new(mixer))
+    }
+}
+
+impl Drop for MixerSource {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Source sending constant frames, optionally keeping its sender open afterwards
+    struct ConstantSource {
+        value: f32,
+        format: AudioFormat,
+        frames: usize,
+        stall: bool,
+        device: Option<String>,
+        /// Capture time of the first frame
+        start: Instant,
+        sender: Option<RingSender<AudioFrame>>,
+    }
+    
+    /// Common start time of the test sources
+    fn epoch() -> Instant {
+        static EPOCH: std::sync::OnceLock<Instant> = std::sync::OnceLock::new();
+        *EPOCH.get_or_init(Instant::now)
+    }
+    
+    impl ConstantSource {
+        fn new(value: f32, format: AudioFormat, frames: usize) -> Self {
+            Self { value, format, frames, stall: false, device: None, start: epoch(), sender: None }
+        }
+    }
+    
+    impl AudioSource for ConstantSource {
+        fn start(&mut self, sender: RingSender<AudioFrame>) -> Result<()> {
+            for index in 0..self.frames {
+                let offset = Duration::from_secs_f64((index * 256) as f64 / self.format.sample_rate as f64);
+                let frame = AudioFrame {
+                    samples: vec![self.value; 256 * self.format.channels as usize],
+                    timestamp: self.start + offset,
+                    sample_rate: self.format.sample_rate,
+                    channels: self.format.channels,
+                };
+                sender.send(frame)?;
+            }
+            if self.stall {
+                self.sender = Some(sender);
+            }
+            Ok(())
+        }
+        
+        fn stop(&mut self) -> Result<()> {
+            self.sender = None;
+            Ok(())
+        }
+        
+        fn describe(&self) -> String {
+            match &self.device {
+                Some(device) => format!("Constant on {}", device),
+                None => "Constant".to_string(),
+            }
+        }
+        
+        fn format(&self) -> Option<AudioFormat> {
+            Some(self.format)
+        }
+        
+        fn device_name(&self) -> Option<String> {
+            self.device.clone()
+        }
+        
+        fn with_device(&self, device_name: Option<String>) -> Result<Box<dyn AudioSource>> {
+            let mut source = ConstantSource::new(self.value, self.format, self.frames);
+            source.device = device_name;
+            Ok(Box::new(source))
+        }
+    }
+    
+    #[test]
+    fn test_mix_with_gain_and_resampling() {
+        let output = AudioFormat { sample_rate: 44100, channels: 2 };
+        let mono = ConstantSource::new(0.25, AudioFormat { sample_rate: 44100, channels: 1 }, 4);
+        let stereo = ConstantSource::new(0.5, AudioFormat { sample_rate: 48000, channels: 2 }, 5);
+        let mut mixer = MixerSource::new(output, 256)
+            .with_source(Box::new(mono), 1.0)
+            .with_source(Box::new(stereo), 0.5);
+        
+        let (sender, receiver) = ring_channel(16);
+        mixer.start(sender).unwrap();
+        let frames: Vec<AudioFrame> = receiver.iter().collect();
+        
+        // 4 full buffers of both sources, then the rest of the longer one padded with silence
+        assert_eq!(frames.len(), 5);
+        for frame in &frames[..4] {
+            assert_eq!(frame.samples.len(), 512);
+            assert_eq!(frame.sample_rate, 44100);
+            assert!(frame.samples.iter().all(|&sample| (sample - 0.5).abs() < 1e-6));
+        }
+        assert!((frames[4].samples[0] - 0.25).abs() < 1e-6);
+        assert_eq!(*frames[4].samples.last().unwrap(), 0.0);
+    }
+    
+    #[test]
+    fn test_sources_are_aligned_on_timestamps() {
+        let format = AudioFormat { sample_rate: 44100, channels: 1 };
+        let buffer = Duration::from_secs_f64(256.0 / 44100.0);
+        let early = ConstantSource::new(0.25, format, 8);
+        let mut late = ConstantSource::new(0.5, format, 6);
+        late.start = early.start + buffer * 2;
+        let mut mixer = MixerSource::new(format, 256)
+            .with_source(Box::new(late), 1.0)
+            .with_source(Box::new(early), 1.0);
+        
+        let (sender, receiver) = ring_channel(16);
+        mixer.start(sender).unwrap();
+        let frames: Vec<AudioFrame> = receiver.iter().collect();
+        
+        // The late source only joins in from its own start, two buffers in
+        assert_eq!(frames.len(), 8);
+        for (index, frame) in frames.iter().enumerate() {
+            let expected = if index < 2 { 0.25 } else { 0.75 };
+            assert!(frame.samples.iter().all(|&sample| (sample - expected).abs() < 1e-6), "frame {}", index);
+            let offset = frame.timestamp.duration_since(epoch()).as_secs_f64() - (buffer * index as u32).as_secs_f64();
+            assert!(offset.abs() < 1e-6, "frame {} is {} s off", index, offset);
+        }
+    }
+    
+    #[test]
+    fn test_stalled_source_is_padded() {
+        let format = AudioFormat { sample_rate: 44100, channels: 1 };
+        let mut stalled = ConstantSource::new(0.0, format, 0);
+        stalled.stall = true;
+        let mut mixer = MixerSource::new(format, 256)
+            .with_source(Box::new(ConstantSource::new(0.25, format, 8)), 1.0)
+            .with_source(Box::new(stalled), 1.0);
+        
+        let (sender, receiver) = ring_channel(16);
+        mixer.start(sender).unwrap();
+        
+        // Once the live source is far enough ahead it is mixed on its own
+        let frame = receiver.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert!(frame.samples.iter().all(|&sample| (sample - 0.25).abs() < 1e-6));
+        
+        mixer.stop().unwrap();
+    }
+    
+    #[test]
+    fn test_device_switch_keeps_other_sources() {
+        let format = AudioFormat { sample_rate: 44100, channels: 2 };
+        let on = |device: &str| {
+            let mut source = ConstantSource::new(0.0, format, 0);
+            source.device = Some(device.to_string());
+            Box::new(source)
+        };
+        let mixer = MixerSource::new(format, 256)
+            .with_device_source(on("Mic"), 1.0)
+            .with_source(on("Speakers"), 0.5);
+        
+        // Only the microphone moves, twice over
+        let switched = mixer.with_device(Some("Headset".to_string())).unwrap();
+        let switched = switched.with_device(Some("Webcam".to_string())).unwrap();
+        assert_eq!(switched.describe(), "Mix: Constant on Webcam x1.00 + Constant on Speakers x0.50");
+    }
+}
@@ -1,16 +1,45 @@
 use anyhow::Result;
 use crossbeam_channel::{Receiver, Sender};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 pub mod capture;
 pub mod analysis;
 pub mod input;
+pub mod source;
+pub mod file;
+pub mod synthetic;
+pub mod stdin;
+pub mod mixer;
+pub mod resample;
+pub mod channels;
+pub mod chroma;
+pub mod ring;
+pub mod latency;
+pub mod recorder;
+pub mod network;
+pub mod agc;
+pub mod beat;
+pub mod filterbank;
+pub mod levels;
+pub mod onset;
+pub mod resolution;
+pub mod trace;
+pub mod window;
 
 pub use capture::AudioCaptureSystem;
-pub use analysis::{AudioAnalyzer, FrequencyData, AudioFeatures};
+pub use source::{AudioSource, create_source};
+pub use analysis::{AudioAnalyzer, FrequencyData, AudioFeatures, StereoFeatures};
+pub use ring::{ring_channel, QueueStats, RingReceiver, RingSender};
+pub use latency::LatencyCompensator;
+pub use recorder::AudioRecorder;
+pub use trace::{TraceRecorder, TraceReplay};
 
-use crate::config::AudioConfig;
+use crate::config::{AudioCaptureMode, AudioConfig};
+
+/// How often the device watchdog looks for unplugged or returning devices
+const DEVICE_WATCHDOG_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
 
 /// Raw audio sample data
 pub type AudioSample = f32;
@@ -25,7 +54,10 @@ pub struct AudioFrame {
 }
 
 /// Processed audio data ready for visualization
-#[derive(Debug, Clone)]
+///
+/// Serializable like the features it carries, without the capture time and
+/// queue state. Traces store a leaner form of it, see `TraceRecorder`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AudioData {
     /// Time domain samples (waveform)
     pub waveform: Vec<AudioSample>,
@@ -36,15 +68,50 @@ pub struct AudioData {
     /// Extracted audio features
     pub features: AudioFeatures,
     
+    /// Spectrum magnitude in the configured filterbank bands, lowest first
+    #[serde(default)]
+    pub bands: Vec<f32>,
+    
+    /// Left channel (same as the right one for mono sources)
+    pub left: ChannelData,
+    
+    /// Right channel
+    pub right: ChannelData,
+    
+    /// Width, balance and correlation of the two channels
+    pub stereo: StereoFeatures,
+    
     /// Timestamp when this data was captured
+    #[serde(skip, default = "std::time::Instant::now")]
     pub timestamp: std::time::Instant,
+    
+    /// State of the audio queues when this data was produced
+    #[serde(skip)]
+    pub queue_stats: AudioQueueStats,
+}
+
+/// Waveform and spectrum of a single channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelData {
+    pub waveform: Vec<AudioSample>,
+    pub spectrum: FrequencyData,
+}
+
+/// Fill levels and drop counters of the audio pipeline queues
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AudioQueueStats {
+    /// Captured frames waiting for analysis
+    pub frames: QueueStats,
+    
+    /// Events waiting for the renderer
+    pub events: QueueStats,
 }
 
 /// Audio system events
 #[derive(Debug, Clone)]
 pub enum AudioEvent {
-    /// New audio data is available
-    DataReady(AudioData),
+    /// New audio data is available, boxed so the other events stay small
+    DataReady(Box<AudioData>),
     
     /// Audio device changed
     DeviceChanged(String),
@@ -55,6 +122,12 @@ pub enum AudioEvent {
     /// System going to sleep/wake
     SystemSuspend,
     SystemResume,
+    
+    /// Audio stayed below the silence threshold for the hold time
+    SilenceStarted,
+    
+    /// Audio rose above the silence threshold again
+    SilenceEnded,
 }
 
 /// Main audio system coordinator
@@ -62,10 +135,17 @@ pub struct AudioSystem {
     config: AudioConfig,
     capture_system: Arc<AudioCaptureSystem>,
     analyzer: Arc<RwLock<AudioAnalyzer>>,
+    recorder: AudioRecorder,
+    trace_recorder: TraceRecorder,
+    
+    // Replaces capture and analysis when set
+    replay: Mutex<Option<TraceReplay>>,
     
     // Communication channels
-    event_sender: Sender<AudioEvent>,
-    event_receiver: Receiver<AudioEvent>,
+    event_sender: RingSender<AudioEvent>,
+    event_receiver: RingReceiver<AudioEvent>,
+    control_sender: Sender<AudioEvent>,
+    control_receiver: Receiver<AudioEvent>,
     
     // State
     is_running: Arc<RwLock<bool>>,
@@ -73,21 +153,43 @@ pub struct AudioSystem {
 }
 
 impl AudioSystem {
-    /// Create a new audio system
-    pub fn new(config: &AudioConfig) -> Result<Self> {
-        let (event_sender, event_receiver) = crossbeam_channel::unbounded();
+    /// Create a new audio system fed by the given audio source
+    pub fn new(config: &AudioConfig, source: Box<dyn AudioSource>) -> Result<Self> {
+        // Bounded so a stalled renderer only ever sees the latest data
+        let (event_sender, event_receiver) = ring_channel(config.event_queue_size);
+        // Unbounded, so device, error and silence events are never dropped for newer data
+        let (control_sender, control_receiver) = crossbeam_channel::unbounded();
+        
+        let capture_system = Arc::new(AudioCaptureSystem::new(source, config.frame_queue_size)?);
+        let recorder = AudioRecorder::new();
+        capture_system.record_to(recorder.clone());
+        
+        // Device backed sources follow the configured device, then the priority list
+        if matches!(config.capture_mode, AudioCaptureMode::Input | AudioCaptureMode::Loopback | AudioCaptureMode::Both) {
+            let mut priority: Vec<String> = config.device_name.iter().cloned().collect();
+            for device in &config.device_priority {
+                if !priority.contains(device) {
+                    priority.push(device.clone());
+                }
+            }
+            capture_system.watch_devices(config.device_name.clone(), priority);
+        }
         
-        let capture_system = Arc::new(AudioCaptureSystem::new(config)?);
         let analyzer = Arc::new(RwLock::new(AudioAnalyzer::new(config)?));
         
         Ok(Self {
             config: config.clone(),
             capture_system,
             analyzer,
+            recorder,
+            trace_recorder: TraceRecorder::new(),
+            replay: Mutex::new(None),
             event_sender,
             event_receiver,
+            control_sender,
+            control_receiver,
             is_running: Arc::new(RwLock::new(false)),
-            current_device: Arc::new(RwLock::new(None)),
+            current_device: Arc::new(RwLock::new(config.device_name.clone())),
         })
     }
     
@@ -97,15 +199,52 @@ impl AudioSystem {
         
         *self.is_running.write() = true;
         
+        // A trace replay stands in for capture and analysis
+        if let Some(replay) = self.replay.lock().take() {
+            let event_sender = self.event_sender.clone();
+            let control_sender = self.control_sender.clone();
+            let is_running = Arc::clone(&self.is_running);
+            
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = replay.run(&event_sender, &is_running) {
+                    log::error!("Trace replay failed: {:#}", e);
+                    let _ = control_sender.send(AudioEvent::Error(format!("Trace replay failed: {}", e)));
+                }
+            });
+            
+            return Ok(());
+        }
+        
         // Start audio capture
         let _capture_handle = {
             let capture_system = Arc::clone(&self.capture_system);
             let analyzer = Arc::clone(&self.analyzer);
+            let trace_recorder = self.trace_recorder.clone();
             let event_sender = self.event_sender.clone();
+            let control_sender = self.control_sender.clone();
             let is_running = Arc::clone(&self.is_running);
             
             tokio::spawn(async move {
-                Self::audio_processing_loop(capture_system, analyzer, event_sender, is_running).await
+                Self::audio_processing_loop(
+                    capture_system,
+                    analyzer,
+                    trace_recorder,
+                    event_sender,
+                    control_sender,
+                    is_running,
+                ).await
+            })
+        };
+        
+        // Watch for unplugged and returning devices
+        let _watchdog_handle = {
+            let capture_system = Arc::clone(&self.capture_system);
+            let control_sender = self.control_sender.clone();
+            let is_running = Arc::clone(&self.is_running);
+            let current_device = Arc::clone(&self.current_device);
+            
+            tokio::spawn(async move {
+                Self::device_watchdog_loop(capture_system, control_sender, is_running, current_device).await
             })
         };
         
@@ -122,15 +261,51 @@ impl AudioSystem {
         // Stop capture system
         self.capture_system.stop().await?;
         
+        // Finish the recordings so the files are complete
+        self.recorder.stop();
+        self.trace_recorder.stop();
+        
         log::info!("Audio system stopped");
         Ok(())
     }
     
     /// Get the event receiver for audio events
-    pub fn event_receiver(&self) -> Receiver<AudioEvent> {
+    ///
+    /// Carries only `AudioEvent::DataReady`, dropping the oldest data when the
+    /// renderer falls behind. Everything else comes from `control_receiver`.
+    pub fn event_receiver(&self) -> RingReceiver<AudioEvent> {
         self.event_receiver.clone()
     }
     
+    /// Get the receiver for device, error and silence events
+    ///
+    /// Never drops events, and each is sent before any data that follows it,
+    /// so draining it before `event_receiver` keeps them in order.
+    pub fn control_receiver(&self) -> Receiver<AudioEvent> {
+        self.control_receiver.clone()
+    }
+    
+    /// Recorder writing the captured audio to WAV files
+    ///
+    /// Recordings hold the raw frames as the source sends them, before the
+    /// frame queue, so frames dropped when analysis falls behind are still in
+    /// the file and a session can be re-rendered later with `--input-file`.
+    pub fn recorder(&self) -> AudioRecorder {
+        self.recorder.clone()
+    }
+    
+    /// Recorder writing the analyzed data to trace files
+    pub fn trace_recorder(&self) -> TraceRecorder {
+        self.trace_recorder.clone()
+    }
+    
+    /// Replay a recorded trace instead of capturing and analyzing audio
+    ///
+    /// Takes effect on the next `start`.
+    pub fn replay_trace(&self, replay: TraceReplay) {
+        *self.replay.lock() = Some(replay);
+    }
+    
     /// Check if the audio system is running
     pub fn is_running(&self) -> bool {
         *self.is_running.read()
@@ -142,54 +317,97 @@ impl AudioSystem {
     }
     
     /// Change the audio input device
+    ///
+    /// The processing loop keeps its frame receiver and the analyzer keeps its
+    /// state. `AudioEvent::DeviceChanged` is only sent once the new device runs.
+    /// The device is opened on a blocking thread, off the async workers.
     pub async fn change_device(&self, device_name: Option<String>) -> Result<()> {
         log::info!("Changing audio device to: {:?}", device_name);
         
-        // Stop current capture
-        self.capture_system.stop().await?;
-        
-        // Update device
-        self.capture_system.set_device(device_name.clone()).await?;
-        
-        // Restart capture
-        self.capture_system.start().await?;
+        let switch = {
+            let capture_system = Arc::clone(&self.capture_system);
+            let device_name = device_name.clone();
+            tokio::task::spawn_blocking(move || capture_system.set_device(device_name))
+        };
+        if let Err(e) = switch.await.unwrap_or_else(|e| Err(e.into())) {
+            log::error!("Failed to change audio device, keeping the current one: {:#}", e);
+            return Err(e);
+        }
         
         *self.current_device.write() = device_name.clone();
         
         // Notify listeners
         let device_name = device_name.unwrap_or_else(|| "Default".to_string());
-        let _ = self.event_sender.send(AudioEvent::DeviceChanged(device_name));
+        let _ = self.control_sender.send(AudioEvent::DeviceChanged(device_name));
         
         Ok(())
     }
     
+    /// Periodically let the capture system move to the best available device
+    ///
+    /// Device enumeration and stream opening block, so each check runs on a
+    /// blocking thread.
+    async fn device_watchdog_loop(
+        capture_system: Arc<AudioCaptureSystem>,
+        control_sender: Sender<AudioEvent>,
+        is_running: Arc<RwLock<bool>>,
+        current_device: Arc<RwLock<Option<String>>>,
+    ) {
+        let mut interval = tokio::time::interval(DEVICE_WATCHDOG_INTERVAL);
+        
+        while *is_running.read() {
+            interval.tick().await;
+            
+            let check = {
+                let capture_system = Arc::clone(&capture_system);
+                tokio::task::spawn_blocking(move || capture_system.check_devices())
+            };
+            match check.await.unwrap_or_else(|e| Err(e.into())) {
+                Ok(true) => {
+                    let device = capture_system.current_device();
+                    *current_device.write() = device.clone();
+                    
+                    let device_name = device.unwrap_or_else(|| "Default".to_string());
+                    log::info!("Audio device changed to: {}", device_name);
+                    let _ = control_sender.send(AudioEvent::DeviceChanged(device_name));
+                }
+                Ok(false) => {}
+                Err(e) => log::warn!("Device watchdog could not switch devices: {:#}", e),
+            }
+        }
+    }
+    
     /// Main audio processing loop
     async fn audio_processing_loop(
         capture_system: Arc<AudioCaptureSystem>,
         analyzer: Arc<RwLock<AudioAnalyzer>>,
-        event_sender: Sender<AudioEvent>,
+        trace_recorder: TraceRecorder,
+        event_sender: RingSender<AudioEvent>,
+        control_sender: Sender<AudioEvent>,
         is_running: Arc<RwLock<bool>>,
     ) {
         let frame_receiver = match capture_system.start().await {
             Ok(receiver) => receiver,
             Err(e) => {
                 log::error!("Failed to start audio capture: {}", e);
-                let _ = event_sender.send(AudioEvent::Error(format!("Capture failed: {}", e)));
+                let _ = control_sender.send(AudioEvent::Error(format!("Capture failed: {}", e)));
                 return;
             }
         };
         
         log::info!("Audio processing loop started");
         
-        while *is_running.read() {
+        let mut reported = AudioQueueStats::default();
+        
+        'receive: while *is_running.read() {
             // Receive audio frames from capture system
             match frame_receiver.recv() {
                 Ok(frame) => {
-                    // Process the audio frame
-                    let audio_data = {
+                    // Process the audio frame, one result per completed hop
+                    let (analyzed, analysis_events) = {
                         let mut analyzer = analyzer.write();
                         match analyzer.process_frame(&frame) {
-                            Ok(data) => data,
+                            Ok(analyzed) => (analyzed, analyzer.take_events()),
                             Err(e) => {
                                 log::warn!("Audio analysis failed: {}", e);
                                 continue;
@@ -197,18 +415,35 @@ impl AudioSystem {
                         }
                     };
                     
+                    let queue_stats = AudioQueueStats {
+                        frames: frame_receiver.stats(),
+                        events: event_sender.stats(),
+                    };
+                    Self::report_overruns(&reported, &queue_stats);
+                    reported = queue_stats;
+                    
+                    // Silence events go first, so data after silence ends never reads as idle
+                    for event in analysis_events {
+                        let _ = control_sender.send(event);
+                    }
+                    
                     // Send processed data to listeners
-                    if let Err(e) = event_sender.send(AudioEvent::DataReady(audio_data)) {
-                        log::warn!("Failed to send audio data: {}", e);
-                        // If the channel is disconnected, exit the loop gracefully
-                        break;
+                    for mut audio_data in analyzed {
+                        audio_data.queue_stats = queue_stats;
+                        trace_recorder.write(&audio_data);
+                        
+                        if let Err(e) = event_sender.send(AudioEvent::DataReady(Box::new(audio_data))) {
+                            log::warn!("Failed to send audio data: {}", e);
+                            // If the channel is disconnected, exit the loop gracefully
+                            break 'receive;
+                        }
                     }
                 }
                 Err(e) => {
                     if *is_running.read() {
                         log::error!("Audio frame receive error: {}", e);
                         // Try to send error event, but don't fail if channel is disconnected
-                        let _ = event_sender.send(AudioEvent::Error(format!("Receive error: {}", e)));
+                        let _ = control_sender.send(AudioEvent::Error(format!("Receive error: {}", e)));
                     }
                     break;
                 }
@@ -217,13 +452,74 @@ impl AudioSystem {
         
         log::info!("Audio processing loop stopped");
     }
+    
+    /// Warn once per overrun, so a stalled consumer doesn't flood the log
+    fn report_overruns(previous: &AudioQueueStats, current: &AudioQueueStats) {
+        if current.frames.overruns > previous.frames.overruns {
+            log::warn!(
+                "Audio analysis fell behind capture, dropping old frames ({} dropped so far)",
+                current.frames.dropped
+            );
+        }
+        if current.events.overruns > previous.events.overruns {
+            log::warn!(
+                "Renderer fell behind audio analysis, dropping old events ({} dropped so far)",
+                current.events.dropped
+            );
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::source::AudioFormat;
     use crate::config::AudioCaptureMode;
     
+    /// Source that replays a fixed list of frames without touching any hardware
+    struct FakeSource {
+        frames: Vec<AudioFrame>,
+    }
+    
+    fn test_frames(count: usize) -> Vec<AudioFrame> {
+        (0..count)
+            .map(|_| AudioFrame {
+                samples: vec![0.25; 1024],
+                timestamp: std::time::Instant::now(),
+                sample_rate: 44100,
+                channels: 1,
+            })
+            .collect()
+    }
+    
+    impl AudioSource for FakeSource {
+        fn start(&mut self, sender: RingSender<AudioFrame>) -> Result<()> {
+            for frame in self.frames.drain(..) {
+                sender.send(frame)?;
+            }
+            Ok(())
+        }
+        
+        fn stop(&mut self) -> Result<()> {
+            Ok(())
+        }
+        
+        fn describe(&self) -> String {
+            "Fake".to_string()
+        }
+        
+        fn format(&self) -> Option<AudioFormat> {
+            Some(AudioFormat { sample_rate: 44100, channels: 1 })
+        }
+        
+        fn with_device(&self, device_name: Option<String>) -> Result<Box<dyn AudioSource>> {
+            match device_name.as_deref() {
+                Some("Missing") => anyhow::bail!("Device not found"),
+                _ => Ok(Box::new(FakeSource { frames: test_frames(2) })),
+            }
+        }
+    }
+    
     #[tokio::test]
     async fn test_audio_system_creation() {
         let config = AudioConfig {
@@ -234,9 +530,96 @@ mod tests {
             capture_mode: AudioCaptureMode::Loopback,
             enable_loopback: true,
             target_latency_ms: 50.0,
+            ..AudioConfig::default()
         };
         
-        let audio_system = AudioSystem::new(&config);
+        let audio_system = AudioSystem::new(&config, Box::new(FakeSource { frames: Vec::new() }));
         assert!(audio_system.is_ok());
     }
-}
\ No newline at end of file
+    
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_audio_system_with_injected_source() {
+        let config = AudioConfig {
+            sample_rate: 44100,
+            buffer_size: 1024,
+            fft_size: 2048,
+            ..AudioConfig::default()
+        };
+        
+        let frames = test_frames(3);
+        let audio_system = AudioSystem::new(&config, Box::new(FakeSource { frames })).unwrap();
+        let events = audio_system.event_receiver();
+        audio_system.start().await.unwrap();
+        
+        for _ in 0..3 {
+            match events.recv_timeout(std::time::Duration::from_secs(2)) {
+                Ok(AudioEvent::DataReady(data)) => assert_eq!(data.waveform.len(), 1024),
+                other => panic!("Unexpected audio event: {:?}", other),
+            }
+        }
+        
+        audio_system.stop().await.unwrap();
+    }
+    
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_recording_keeps_dropped_frames() {
+        // The source sends all its frames at once, overflowing a single frame slot
+        let config = AudioConfig {
+            capture_mode: AudioCaptureMode::Synthetic,
+            frame_queue_size: 1,
+            ..AudioConfig::default()
+        };
+        let path = std::env::temp_dir().join("wcr_viz_dropped_frames_test.wav");
+        let audio_system = AudioSystem::new(&config, Box::new(FakeSource { frames: test_frames(5) })).unwrap();
+        audio_system.recorder().start(&path).unwrap();
+        audio_system.start().await.unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        audio_system.stop().await.unwrap();
+        
+        let len = std::fs::metadata(&path).unwrap().len();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(len, 44 + 5 * 1024 * 4);
+    }
+    
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_change_device_keeps_processing() {
+        // Not a device capture mode, so the device watchdog stays out of the way,
+        // a hop per frame, so each frame gives a single result, and a single
+        // event slot, so data keeps overwriting itself
+        let config = AudioConfig {
+            capture_mode: AudioCaptureMode::Synthetic,
+            hop_size: 1024,
+            event_queue_size: 1,
+            ..AudioConfig::default()
+        };
+        let audio_system = AudioSystem::new(&config, Box::new(FakeSource { frames: test_frames(1) })).unwrap();
+        let events = audio_system.event_receiver();
+        let controls = audio_system.control_receiver();
+        audio_system.start().await.unwrap();
+        
+        let wait_for_data = || match events.recv_timeout(std::time::Duration::from_secs(2)) {
+            Ok(AudioEvent::DataReady(_)) => {}
+            other => panic!("Unexpected audio event: {:?}", other),
+        };
+        wait_for_data();
+        
+        // A failed switch keeps the current device and sends no event
+        assert!(audio_system.change_device(Some("Missing".to_string())).await.is_err());
+        assert!(controls.try_recv().is_err());
+        assert_eq!(audio_system.current_device(), None);
+        
+        // The new device feeds the same processing loop, and its event outlives
+        // the data that overflows the event ring meanwhile
+        audio_system.change_device(Some("Other".to_string())).await.unwrap();
+        assert_eq!(audio_system.current_device(), Some("Other".to_string()));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        
+        match controls.try_recv() {
+            Ok(AudioEvent::DeviceChanged(name)) => assert_eq!(name, "Other"),
+            other => panic!("Unexpected control event: {:?}", other),
+        }
+        wait_for_data();
+        
+        audio_system.stop().await.unwrap();
+    }
+}
@@ -0,0 +1,435 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use super::ring::RingSender;
+use super::source::{AudioFormat, AudioSource};
+use super::stdin::decode_pcm;
+use super::AudioFrame;
+use crate::config::{NetworkPayload, PcmEncoding, PcmFormat};
+
+/// Largest datagram that can be received
+const MAX_DATAGRAM_LEN: usize = 65536;
+
+/// Longest the receive loop blocks before checking whether it should stop or play
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Gain applied to the repeated audio for every consecutive lost packet
+const CONCEALMENT_DECAY: f32 = 0.5;
+
+/// Buffered audio, in multiples of the jitter target, above which old packets are skipped
+const MAX_BUFFER_FACTOR: usize = 4;
+
+/// Audio source receiving PCM packets over UDP
+///
+/// Accepts RTP packets with L16 payloads or bare datagrams of raw PCM. Packets
+/// go through a jitter buffer that puts them back in order and replaces lost
+/// ones with a fading copy of the previous packet, and are played out at the
+/// stream's sample rate.
+pub struct NetworkSource {
+    address: SocketAddr,
+    payload: NetworkPayload,
+    pcm_format: PcmFormat,
+    jitter: Duration,
+    buffer_size: usize,
+    local_address: Option<SocketAddr>,
+    is_running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Reorders packets and conceals lost ones
+struct JitterBuffer {
+    /// Packets waiting to be played, by extended sequence number
+    packets: BTreeMap<u64, Vec<f32>>,
+    /// Extended sequence number of the next packet to play
+    next: Option<u64>,
+    /// Highest extended sequence number seen, used to unwrap 16-bit sequence numbers
+    highest: Option<u64>,
+    /// Samples to buffer before playback starts
+    target_len: usize,
+    buffered_len: usize,
+    playing: bool,
+    last_played: Vec<f32>,
+    concealed_run: i32,
+    lost: u64,
+    late: u64,
+}
+
+impl JitterBuffer {
+    fn new(target_len: usize) -> Self {
+        Self {
+            packets: BTreeMap::new(),
+            next: None,
+            highest: None,
+            target_len,
+            buffered_len: 0,
+            playing: false,
+            last_played: Vec::new(),
+            concealed_run: 0,
+            lost: 0,
+            late: 0,
+        }
+    }
+    
+    /// Queue a packet
+    fn push(&mut self, sequence: u16, samples: Vec<f32>) {
+        // Start one wrap in, so packets reordered before the first one still sort before it
+        let extended = match self.highest {
+            None => sequence as u64 + (1 << 16),
+            Some(highest) => {
+                let delta = sequence.wrapping_sub(highest as u16) as i16 as i64;
+                (highest as i64 + delta).max(0) as u64
+            }
+        };
+        self.highest = Some(self.highest.map_or(extended, |highest| highest.max(extended)));
+        
+        if self.next.is_some_and(|next| extended < next) {
+            self.late += 1;
+            return;
+        }
+        
+        self.buffered_len += samples.len();
+        if let Some(duplicate) = self.packets.insert(extended, samples) {
+            self.buffered_len -= duplicate.len();
+        }
+        
+        // The sender's clock runs faster than ours: skip old audio to keep the delay bounded
+        while self.buffered_len > self.target_len * MAX_BUFFER_FACTOR && self.packets.len() > 1 {
+            if let Some((sequence, skipped)) = self.packets.pop_first() {
+                self.buffered_len -= skipped.len();
+                self.next = Some(sequence + 1);
+            }
+        }
+    }
+    
+    /// Audio of the next packet, concealed if it was lost, or None while buffering
+    fn pop(&mut self) -> Option<Vec<f32>> {
+        if !self.playing {
+            if self.buffered_len < self.target_len.max(1) {
+                return None;
+            }
+            self.playing = true;
+            self.next = self.packets.keys().next().copied();
+        }
+        
+        let next = self.next?;
+        if let Some(samples) = self.packets.remove(&next) {
+            self.buffered_len -= samples.len();
+            self.next = Some(next + 1);
+            self.concealed_run = 0;
+            self.last_played.clone_from(&samples);
+            return Some(samples);
+        }
+        
+        // Nothing left at all: the stream paused or stalled, buffer up again
+        if self.packets.is_empty() {
+            self.playing = false;
+            return None;
+        }
+        
+        // A later packet arrived, so this one is lost: repeat the previous one, fading out
+        self.lost += 1;
+        self.concealed_run += 1;
+        self.next = Some(next + 1);
+        let gain = CONCEALMENT_DECAY.powi(self.concealed_run);
+        Some(self.last_played.iter().map(|sample| sample * gain).collect())
+    }
+}
+
+/// Decode an RTP packet with an L16 payload into its sequence number, format and samples
+fn parse_rtp(datagram: &[u8], default_format: AudioFormat) -> Option<(u16, AudioFormat, Vec<f32>)> {
+    if datagram.len() < 12 || datagram[0] >> 6 != 2 {
+        return None;
+    }
+    
+    let padding = datagram[0] & 0x20 != 0;
+    let extension = datagram[0] & 0x10 != 0;
+    let csrc_count = (datagram[0] & 0x0f) as usize;
+    let payload_type = datagram[1] & 0x7f;
+    let sequence = u16::from_be_bytes([datagram[2], datagram[3]]);
+    
+    let mut start = 12 + 4 * csrc_count;
+    if extension {
+        let header = datagram.get(start..start + 4)?;
+        start += 4 + 4 * u16::from_be_bytes([header[2], header[3]]) as usize;
+    }
+    let end = if padding {
+        datagram.len().checked_sub(*datagram.last()? as usize)?
+    } else {
+        datagram.len()
+    };
+    let payload = datagram.get(start..end)?;
+    
+    // Static L16 payload types, dynamic ones carry the configured format
+    let format = match payload_type {
+        10 => AudioFormat { sample_rate: 44100, channels: 2 },
+        11 => AudioFormat { sample_rate: 44100, channels: 1 },
+        _ => default_format,
+    };
+    
+    Some((sequence, format, decode_pcm(payload, PcmEncoding::S16Be)))
+}
+
+impl NetworkSource {
+    /// Receive `payload` packets on `address`, buffering `jitter_ms` of audio against jitter
+    ///
+    /// `pcm_format` is the layout of raw packets and the rate and channel count
+    /// of RTP packets with a dynamic payload type.
+    pub fn new(
+        address: SocketAddr,
+        payload: NetworkPayload,
+        pcm_format: PcmFormat,
+        jitter_ms: f32,
+        buffer_size: usize,
+    ) -> Self {
+        Self {
+            address,
+            payload,
+            pcm_format,
+            jitter: Duration::from_secs_f32(jitter_ms.max(0.0) / 1000.0),
+            buffer_size: buffer_size.max(1),
+            local_address: None,
+            is_running: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+    
+    /// Address the socket is bound to, once started
+    pub fn local_address(&self) -> Option<SocketAddr> {
+        self.local_address
+    }
+    
+    /// Receive loop, runs until stopped, the receiver goes away or the socket fails
+    fn receive_loop(
+        socket: UdpSocket,
+        payload: NetworkPayload,
+        pcm_format: PcmFormat,
+        jitter: Duration,
+        buffer_size: usize,
+        sender: RingSender<AudioFrame>,
+        is_running: Arc<AtomicBool>,
+    ) {
+        let default_format = AudioFormat {
+            sample_rate: pcm_format.sample_rate,
+            channels: pcm_format.channels,
+        };
+        let mut datagram = vec![0u8; MAX_DATAGRAM_LEN];
+        let mut format: Option<AudioFormat> = None;
+        let mut jitter_buffer = JitterBuffer::new(0);
+        let mut raw_sequence: u16 = 0;
+        // Played out samples waiting to fill a frame
+        let mut pending: Vec<f32> = Vec::new();
+        let mut next_frame_time: Option<Instant> = None;
+        
+        while is_running.load(Ordering::Relaxed) {
+            let wait = next_frame_time
+                .map_or(POLL_INTERVAL, |time| time.saturating_duration_since(Instant::now()).min(POLL_INTERVAL))
+                .max(Duration::from_millis(1));
+            let _ = socket.set_read_timeout(Some(wait));
+            
+            match socket.recv(&mut datagram) {
+                Ok(len) => {
+                    let packet = match payload {
+                        NetworkPayload::Rtp => parse_rtp(&datagram[..len], default_format),
+                        NetworkPayload::Raw => {
+                            raw_sequence = raw_sequence.wrapping_add(1);
+                            let samples = decode_pcm(&datagram[..len], pcm_format.encoding);
+                            Some((raw_sequence, default_format, samples))
+                        }
+                    };
+                    
+                    if let Some((sequence, packet_format, mut samples)) = packet {
+                        if format != Some(packet_format) {
+                            log::info!("Receiving network audio ({})", packet_format);
+                            let target_len = (jitter.as_secs_f64() * packet_format.sample_rate as f64) as usize
+                                * packet_format.channels.max(1) as usize;
+                            format = Some(packet_format);
+                            jitter_buffer = JitterBuffer::new(target_len);
+                            pending.clear();
+                            next_frame_time = None;
+                        }
+                        
+                        // Drop a trailing partial sample frame
+                        let channels = packet_format.channels.max(1) as usize;
+                        samples.truncate(samples.len() - samples.len() % channels);
+                        jitter_buffer.push(sequence, samples);
+                    }
+                }
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {}
+                Err(e) => {
+                    log::error!("Network audio receive failed: {}", e);
+                    break;
+                }
+            }
+            
+            let Some(format) = format else { continue };
+            let chunk_len = buffer_size * format.channels.max(1) as usize;
+            let frame_duration = Duration::from_secs_f64(buffer_size as f64 / format.sample_rate as f64);
+            
+            // Play out a frame every frame duration, restarting the clock after buffering
+            while next_frame_time.is_none_or(|time| time <= Instant::now()) {
+                while pending.len() < chunk_len {
+                    match jitter_buffer.pop() {
+                        Some(samples) => pending.extend(samples),
+                        None => break,
+                    }
+                }
+                if pending.len() < chunk_len {
+                    next_frame_time = None;
+                    break;
+                }
+                
+                let frame = AudioFrame {
+                    samples: pending.drain(..chunk_len).collect(),
+                    timestamp: Instant::now(),
+                    sample_rate: format.sample_rate,
+                    channels: format.channels,
+                };
+                if sender.send(frame).is_err() {
+                    return;
+                }
+                
+                next_frame_time = Some(next_frame_time.unwrap_or_else(Instant::now) + frame_duration);
+            }
+        }
+        
+        if jitter_buffer.lost > 0 || jitter_buffer.late > 0 {
+            log::info!(
+                "Network audio: {} packets concealed, {} arrived too late",
+                jitter_buffer.lost,
+                jitter_buffer.late
+            );
+        }
+    }
+}
+
+impl AudioSource for NetworkSource {
+    fn start(&mut self, sender: RingSender<AudioFrame>) -> Result<()> {
+        self.stop()?;
+        
+        let socket = UdpSocket::bind(self.address)
+            .with_context(|| format!("Failed to listen for network audio on {}", self.address))?;
+        self.local_address = Some(socket.local_addr()?);
+        log::info!("Listening for {:?} audio on {}", self.payload, socket.local_addr()?);
+        
+        self.is_running.store(true, Ordering::Relaxed);
+        let thread = {
+            let payload = self.payload;
+            let pcm_format = self.pcm_format;
+            let jitter = self.jitter;
+            let buffer_size = self.buffer_size;
+            let is_running = Arc::clone(&self.is_running);
+            std::thread::Builder::new()
+                .name("audio-network".to_string())
+                .spawn(move || {
+                    Self::receive_loop(socket, payload, pcm_format, jitter, buffer_size, sender, is_running);
+                })
+                .context("Failed to spawn network audio thread")?
+        };
+        
+        self.thread = Some(thread);
+        Ok(())
+    }
+    
+    fn stop(&mut self) -> Result<()> {
+        self.is_running.store(false, Ordering::Relaxed);
+        
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Network audio thread panicked");
+            }
+        }
+        
+        Ok(())
+    }
+    
+    fn describe(&self) -> String {
+        format!("Network: {:?} on {}", self.payload, self.local_address().unwrap_or(self.address))
+    }
+    
+    fn format(&self) -> Option<AudioFormat> {
+        Some(AudioFormat {
+            sample_rate: self.pcm_format.sample_rate,
+            channels: self.pcm_format.channels,
+        })
+    }
+}
+
+impl Drop for NetworkSource {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::ring::ring_channel;
+    
+    fn rtp_packet(sequence: u16, payload_type: u8, samples: &[i16]) -> Vec<u8> {
+        let mut packet = vec![0x80, payload_type];
+        packet.extend_from_slice(&sequence.to_be_bytes());
+        packet.extend_from_slice(&[0; 8]);
+        packet.extend(samples.iter().flat_map(|sample| sample.to_be_bytes()));
+        packet
+    }
+    
+    #[test]
+    fn test_parse_rtp() {
+        let default_format = AudioFormat { sample_rate: 48000, channels: 2 };
+        
+        let packet = rtp_packet(7, 96, &[16384, -16384]);
+        let (sequence, format, samples) = parse_rtp(&packet, default_format).unwrap();
+        assert_eq!(sequence, 7);
+        assert_eq!(format, default_format);
+        assert_eq!(samples, vec![0.5, -0.5]);
+        
+        // Header extension, one CSRC and padding are skipped, PT 11 is 44.1 kHz mono
+        let mut packet = vec![0x80 | 0x20 | 0x10 | 1, 11, 0, 1];
+        packet.extend_from_slice(&[0; 12]);
+        packet.extend_from_slice(&[0xbe, 0xde, 0, 1, 1, 2, 3, 4]);
+        packet.extend_from_slice(&16384i16.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 3]);
+        let (_, format, samples) = parse_rtp(&packet, default_format).unwrap();
+        assert_eq!(format, AudioFormat { sample_rate: 44100, channels: 1 });
+        assert_eq!(samples, vec![0.5]);
+        
+        assert!(parse_rtp(&[0x40; 16], default_format).is_none());
+    }
+    
+    #[test]
+    fn test_network_source_reorders_and_conceals() {
+        let pcm_format: PcmFormat = "s16be:48000:2".parse().unwrap();
+        let mut source = NetworkSource::new("127.0.0.1:0".parse().unwrap(), NetworkPayload::Rtp, pcm_format, 20.0, 256);
+        let (sender, receiver) = ring_channel(32);
+        source.start(sender).unwrap();
+        
+        // Packet i carries (i + 1) / 32 on every sample, sequence numbers wrap around
+        let value = |i: usize| (i + 1) as f32 / 32.0;
+        let packets: Vec<Vec<u8>> = (0..12)
+            .map(|i| rtp_packet(65530u16.wrapping_add(i as u16), 96, &[((i + 1) * 1024) as i16; 512]))
+            .collect();
+        
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = source.local_address().unwrap();
+        for i in [0, 1, 3, 2, 4, 6, 7, 8, 9, 10, 11] {
+            socket.send_to(&packets[i], address).unwrap();
+        }
+        
+        let frames: Vec<AudioFrame> = (0..12)
+            .map(|_| receiver.recv_timeout(Duration::from_secs(2)).unwrap())
+            .collect();
+        source.stop().unwrap();
+        
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.samples.len(), 512);
+            // Packet 5 was lost and is replaced by packet 4 at half the level
+            let expected = if i == 5 { value(4) * CONCEALMENT_DECAY } else { value(i) };
+            assert!(frame.samples.iter().all(|&sample| sample == expected), "frame {} is not {}", i, expected);
+        }
+    }
+}
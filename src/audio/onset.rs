@@ -0,0 +1,221 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// Bands checked for onsets, the same as the band energies in `AudioFeatures` (Hz)
+const ONSET_BANDS: [(f32, f32); 7] = [
+    (20.0, 60.0),
+    (60.0, 250.0),
+    (250.0, 500.0),
+    (500.0, 2000.0),
+    (2000.0, 4000.0),
+    (4000.0, 6000.0),
+    (6000.0, f32::INFINITY),
+];
+
+/// Scale applied to magnitudes before log compression, so quiet and loud transients weigh alike
+const COMPRESSION: f32 = 1000.0;
+
+/// Flux the adaptive threshold looks back over (seconds)
+const FLUX_HISTORY_SECONDS: f32 = 1.0;
+
+/// Adaptive threshold: the median recent flux plus this many median absolute deviations...
+const THRESHOLD_DEVIATIONS: f32 = 5.0;
+
+/// ...plus this offset, which keeps noise in quiet passages from triggering
+const THRESHOLD_OFFSET: f32 = 0.2;
+
+/// Shortest time between two onsets in the same band
+const MIN_ONSET_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Bands with a transient in the current frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BandOnsets {
+    pub sub_bass: bool,
+    pub bass: bool,
+    pub low_mid: bool,
+    pub mid: bool,
+    pub high_mid: bool,
+    pub presence: bool,
+    pub brilliance: bool,
+}
+
+impl BandOnsets {
+    /// Flags from the lowest band to the highest
+    fn from_array(flags: [bool; 7]) -> Self {
+        let [sub_bass, bass, low_mid, mid, high_mid, presence, brilliance] = flags;
+        Self { sub_bass, bass, low_mid, mid, high_mid, presence, brilliance }
+    }
+    
+    /// Bands with an onset in either
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            sub_bass: self.sub_bass || other.sub_bass,
+            bass: self.bass || other.bass,
+            low_mid: self.low_mid || other.low_mid,
+            mid: self.mid || other.mid,
+            high_mid: self.high_mid || other.high_mid,
+            presence: self.presence || other.presence,
+            brilliance: self.brilliance || other.brilliance,
+        }
+    }
+}
+
+/// Adaptive threshold over the recent flux of one band
+struct FluxThreshold {
+    history: VecDeque<f32>,
+    capacity: usize,
+    last_onset: Option<Instant>,
+}
+
+impl FluxThreshold {
+    /// Create a threshold over the last `capacity` frames
+    fn new(capacity: usize) -> Self {
+        Self {
+            history: VecDeque::with_capacity(capacity + 1),
+            capacity,
+            last_onset: None,
+        }
+    }
+    
+    /// Feed the flux of a frame, returning whether it is an onset and how far above the threshold it went (0.0 - 1.0)
+    fn update(&mut self, flux: f32, timestamp: Instant) -> (bool, f32) {
+        let threshold = self.median_and_deviation()
+            .map_or(THRESHOLD_OFFSET, |(median, deviation)| median + THRESHOLD_DEVIATIONS * deviation + THRESHOLD_OFFSET);
+        let strength = ((flux - threshold) / threshold).clamp(0.0, 1.0);
+        
+        let rested = self.last_onset
+            .is_none_or(|last| timestamp.saturating_duration_since(last) >= MIN_ONSET_INTERVAL);
+        let onset = flux > threshold && rested;
+        if onset {
+            self.last_onset = Some(timestamp);
+        }
+        
+        self.history.push_back(flux);
+        if self.history.len() > self.capacity {
+            self.history.pop_front();
+        }
+        
+        (onset, strength)
+    }
+    
+    fn median_and_deviation(&self) -> Option<(f32, f32)> {
+        fn median(mut values: Vec<f32>) -> f32 {
+            values.sort_by(f32::total_cmp);
+            values[values.len() / 2]
+        }
+        if self.history.is_empty() {
+            return None;
+        }
+        let median_flux = median(self.history.iter().copied().collect());
+        let deviation = median(self.history.iter().map(|flux| (flux - median_flux).abs()).collect());
+        Some((median_flux, deviation))
+    }
+}
+
+/// Spectral flux onset detector
+///
+/// Compares each log-compressed magnitude spectrum with the one a window
+/// earlier (the last one that doesn't overlap it) and sums the increases in
+/// each band. A band has an onset when its flux stands out from its recent
+/// flux (an adaptive threshold on the median and spread), so hi-hats and
+/// snares register as well as kicks, and steady sounds don't, however loud.
+pub struct OnsetDetector {
+    band_bins: Vec<Range<usize>>,
+    bands: Vec<FluxThreshold>,
+    /// Compressed magnitudes of the last `lag` frames, oldest first
+    previous: VecDeque<Vec<f32>>,
+    lag: usize,
+    novelty: f32,
+}
+
+impl OnsetDetector {
+    /// Create a detector for spectra with the given bin frequencies, arriving at `frame_rate` Hz
+    ///
+    /// `lag` is the number of frames a window spans, 1 unless windows overlap.
+    pub fn new(bin_frequencies: &[f32], frame_rate: f32, lag: usize) -> Self {
+        let band_bins = ONSET_BANDS
+            .iter()
+            .map(|&(low, high)| {
+                let start = bin_frequencies.iter().position(|&f| f >= low).unwrap_or(bin_frequencies.len());
+                let end = bin_frequencies.iter().position(|&f| f >= high).unwrap_or(bin_frequencies.len());
+                start..end.max(start)
+            })
+            .collect();
+        
+        let history = ((FLUX_HISTORY_SECONDS * frame_rate).round() as usize).max(1);
+        Self {
+            band_bins,
+            bands: ONSET_BANDS.iter().map(|_| FluxThreshold::new(history)).collect(),
+            previous: VecDeque::with_capacity(lag.max(1)),
+            lag: lag.max(1),
+            novelty: 0.0,
+        }
+    }
+    
+    /// Flux of the last frame summed over the bands, the onset envelope beats are tracked on
+    pub fn novelty(&self) -> f32 {
+        self.novelty
+    }
+    
+    /// Feed the magnitude spectrum of a frame, returning the onset strength of the strongest band and the bands with an onset
+    pub fn process(&mut self, bins: &[f32], timestamp: Instant) -> (f32, BandOnsets) {
+        let current: Vec<f32> = bins.iter().map(|&magnitude| (1.0 + COMPRESSION * magnitude).ln()).collect();
+        let Some(previous) = self.previous.front() else {
+            // Nothing to compare the first frame with
+            self.previous.push_back(current);
+            return (0.0, BandOnsets::default());
+        };
+        
+        // Only increases count, energy dying away is not an onset
+        let rise: Vec<f32> = current.iter().zip(previous).map(|(now, before)| (now - before).max(0.0)).collect();
+        if self.previous.len() >= self.lag {
+            self.previous.pop_front();
+        }
+        self.previous.push_back(current);
+        let mean_rise = |bins: Range<usize>| {
+            let bins = bins.start.min(rise.len())..bins.end.min(rise.len());
+            if bins.is_empty() {
+                0.0
+            } else {
+                rise[bins.clone()].iter().sum::<f32>() / bins.len() as f32
+            }
+        };
+        
+        let mut flags = [false; 7];
+        let mut strength = 0.0f32;
+        self.novelty = 0.0;
+        for ((flag, bins), band) in flags.iter_mut().zip(&self.band_bins).zip(&mut self.bands) {
+            let flux = mean_rise(bins.clone());
+            let (onset, band_strength) = band.update(flux, timestamp);
+            *flag = onset;
+            strength = strength.max(band_strength);
+            self.novelty += flux;
+        }
+        
+        (strength, BandOnsets::from_array(flags))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_threshold_follows_recent_flux() {
+        let start = Instant::now();
+        let at = |frame: u64| start + Duration::from_millis(frame * 23);
+        let mut threshold = FluxThreshold::new(43);
+        
+        // A jump from nothing is an onset, a steady level is not, however high
+        assert!(threshold.update(1.0, at(0)).0);
+        for frame in 1..50 {
+            assert!(!threshold.update(1.0, at(frame)).0);
+        }
+        
+        // Well above the recent flux is, but not twice within the minimum interval
+        assert_eq!(threshold.update(5.0, at(50)), (true, 1.0));
+        assert!(!threshold.update(5.0, start + Duration::from_millis(50 * 23 + 10)).0);
+    }
+}
@@ -0,0 +1,333 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::channels::remix;
+use super::resample::Resampler;
+use super::source::AudioFormat;
+use super::AudioFrame;
+
+/// Size of the RIFF/WAVE header written in front of the samples
+const WAV_HEADER_LEN: u32 = 44;
+
+/// WAVE format tag for 32-bit float samples
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+/// Format of a recording that ended before any audio arrived
+const EMPTY_RECORDING_FORMAT: AudioFormat = AudioFormat { sample_rate: 44100, channels: 2 };
+
+/// Frames queued for the writer thread, a few seconds of audio at usual frame sizes
+const FRAME_QUEUE_SIZE: usize = 256;
+
+/// Writes captured audio frames to a WAV file
+///
+/// `write` only queues the frame, without locking or blocking, so it can be
+/// called from a real-time audio callback. A `wav-writer` thread owns the file,
+/// converts and writes the frames, and finishes recordings. If it falls behind
+/// the queue fills up and frames are dropped, which it logs.
+///
+/// Cloning gives another handle to the same recorder, so the capture can write
+/// frames while e.g. a hotkey starts and stops recordings.
+#[derive(Clone)]
+pub struct AudioRecorder {
+    frames: Sender<AudioFrame>,
+    commands: Sender<RecorderCommand>,
+    recording: Arc<AtomicBool>,
+    dropped: Arc<AtomicU64>,
+}
+
+/// Requests for the writer thread, answered once done
+enum RecorderCommand {
+    Start(PathBuf, Sender<Result<()>>),
+    Stop(Sender<Option<PathBuf>>),
+}
+
+impl Default for AudioRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioRecorder {
+    /// Create a recorder that is not recording yet
+    pub fn new() -> Self {
+        let (frames, frame_receiver) = crossbeam_channel::bounded(FRAME_QUEUE_SIZE);
+        let (commands, command_receiver) = crossbeam_channel::unbounded();
+        let recording = Arc::new(AtomicBool::new(false));
+        let dropped = Arc::new(AtomicU64::new(0));
+        
+        let writer = RecorderThread {
+            frames: frame_receiver,
+            commands: command_receiver,
+            recording: Arc::clone(&recording),
+            dropped: Arc::clone(&dropped),
+            active: None,
+        };
+        if let Err(e) = std::thread::Builder::new().name("wav-writer".to_string()).spawn(move || writer.run()) {
+            log::error!("Failed to start the recording thread: {}", e);
+        }
+        
+        Self { frames, commands, recording, dropped }
+    }
+    
+    /// Start recording to `path`, finishing any recording in progress first
+    pub fn start(&self, path: impl Into<PathBuf>) -> Result<()> {
+        let path = path.into();
+        let (done, result) = crossbeam_channel::bounded(1);
+        self.commands.send(RecorderCommand::Start(path.clone(), done))
+            .map_err(|_| anyhow::anyhow!("Recording thread is not running"))?;
+        result.recv().context("Recording thread stopped")??;
+        
+        self.recording.store(true, Ordering::Release);
+        log::info!("Recording audio to {}", path.display());
+        Ok(())
+    }
+    
+    /// Stop recording, returning the path of the finished file
+    pub fn stop(&self) -> Option<PathBuf> {
+        self.recording.store(false, Ordering::Release);
+        let (done, path) = crossbeam_channel::bounded(1);
+        self.commands.send(RecorderCommand::Stop(done)).ok()?;
+        path.recv().ok().flatten()
+    }
+    
+    /// Whether a recording is in progress
+    pub fn is_recording(&self) -> bool {
+        self.recording.load(Ordering::Acquire)
+    }
+    
+    /// Queue a captured frame for the recording in progress, if any
+    ///
+    /// Never blocks: if the writer thread fell behind, the frame is dropped and
+    /// counted. A failing recording is finished and dropped, capture carries on.
+    pub fn write(&self, frame: &AudioFrame) {
+        if !self.is_recording() {
+            return;
+        }
+        
+        if let Err(TrySendError::Full(_)) = self.frames.try_send(frame.clone()) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The `wav-writer` thread, owning the recording in progress
+struct RecorderThread {
+    frames: Receiver<AudioFrame>,
+    commands: Receiver<RecorderCommand>,
+    recording: Arc<AtomicBool>,
+    dropped: Arc<AtomicU64>,
+    active: Option<WavRecording>,
+}
+
+impl RecorderThread {
+    /// Write frames and handle commands until every `AudioRecorder` handle is gone
+    fn run(mut self) {
+        loop {
+            crossbeam_channel::select! {
+                recv(self.commands) -> command => match command {
+                    Ok(RecorderCommand::Start(path, done)) => {
+                        // Frames queued while no recording ran belong to none
+                        self.frames.try_iter().for_each(drop);
+                        let result = WavRecording::create(&path).map(|recording| {
+                            if let Some(previous) = self.active.replace(recording) {
+                                Self::finish(previous);
+                            }
+                        });
+                        let _ = done.send(result);
+                    }
+                    Ok(RecorderCommand::Stop(done)) => {
+                        // Frames sent before the stop still go into the file
+                        while let Ok(frame) = self.frames.try_recv() {
+                            self.write(&frame);
+                        }
+                        let _ = done.send(self.active.take().map(Self::finish));
+                    }
+                    Err(_) => break,
+                },
+                recv(self.frames) -> frame => match frame {
+                    Ok(frame) => self.write(&frame),
+                    Err(_) => break,
+                },
+            }
+        }
+        
+        if let Some(recording) = self.active.take() {
+            Self::finish(recording);
+        }
+    }
+    
+    fn write(&mut self, frame: &AudioFrame) {
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            log::warn!("Audio recording fell behind, dropped {} frames", dropped);
+        }
+        
+        let Some(active) = self.active.as_mut() else {
+            return;
+        };
+        
+        if let Err(e) = active.write(frame) {
+            log::error!("Audio recording failed: {:#}", e);
+            self.recording.store(false, Ordering::Release);
+            if let Some(failed) = self.active.take() {
+                Self::finish(failed);
+            }
+        }
+    }
+    
+    fn finish(mut recording: WavRecording) -> PathBuf {
+        match recording.finish() {
+            Ok(seconds) => log::info!("Recorded {:.1} s of audio to {}", seconds, recording.path.display()),
+            Err(e) => log::error!("Failed to finish recording {}: {:#}", recording.path.display(), e),
+        }
+        recording.path.clone()
+    }
+}
+
+/// A WAV file being written
+///
+/// The format is taken from the first frame. Frames arriving later in another
+/// format, e.g. after switching devices, are converted to it.
+struct WavRecording {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    format: Option<AudioFormat>,
+    resampler: Option<Resampler>,
+    data_len: u32,
+    finished: bool,
+}
+
+impl WavRecording {
+    fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create recording: {}", path.display()))?;
+        
+        // The header is written once the format and length are known
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&[0; WAV_HEADER_LEN as usize])?;
+        
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer,
+            format: None,
+            resampler: None,
+            data_len: 0,
+            finished: false,
+        })
+    }
+    
+    fn write(&mut self, frame: &AudioFrame) -> Result<()> {
+        let format = *self.format.get_or_insert(AudioFormat {
+            sample_rate: frame.sample_rate,
+            channels: frame.channels,
+        });
+        
+        let samples = remix(&frame.samples, frame.channels, format.channels);
+        let samples = if frame.sample_rate == format.sample_rate {
+            self.resampler = None;
+            samples
+        } else {
+            let resampler = match &mut self.resampler {
+                Some(resampler) if resampler.input_rate() == frame.sample_rate => resampler,
+                resampler => resampler.insert(Resampler::new(frame.sample_rate, format.sample_rate, format.channels)),
+            };
+            resampler.process(&samples)
+        };
+        
+        let len = (samples.len() * 4) as u32;
+        if self.data_len.checked_add(len).is_none_or(|total| total > u32::MAX - WAV_HEADER_LEN) {
+            anyhow::bail!("Recording reached the 4 GB WAV size limit");
+        }
+        
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_len += len;
+        
+        Ok(())
+    }
+    
+    /// Write the final header, returning the recorded duration in seconds
+    fn finish(&mut self) -> Result<f64> {
+        self.finished = true;
+        let format = self.format.unwrap_or(EMPTY_RECORDING_FORMAT);
+        let channels = format.channels.max(1);
+        let block_align = channels * 4;
+        
+        let mut header = Vec::with_capacity(WAV_HEADER_LEN as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(WAV_HEADER_LEN - 8 + self.data_len).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&WAVE_FORMAT_IEEE_FLOAT.to_le_bytes());
+        header.extend_from_slice(&channels.to_le_bytes());
+        header.extend_from_slice(&format.sample_rate.to_le_bytes());
+        header.extend_from_slice(&(format.sample_rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&32u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&self.data_len.to_le_bytes());
+        
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header)?;
+        self.writer.flush()?;
+        
+        Ok(self.data_len as f64 / (format.sample_rate as f64 * block_align as f64))
+    }
+}
+
+impl Drop for WavRecording {
+    fn drop(&mut self) {
+        // Keep the file playable if the recorder goes away mid-recording
+        if !self.finished {
+            let _ = self.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::Source;
+    
+    fn frame(value: f32, sample_rate: u32, channels: u16, frames: usize) -> AudioFrame {
+        AudioFrame {
+            samples: vec![value; frames * channels as usize],
+            timestamp: std::time::Instant::now(),
+            sample_rate,
+            channels,
+        }
+    }
+    
+    #[test]
+    fn test_recording_round_trip() {
+        let path = std::env::temp_dir().join("wcr_viz_recorder_test.wav");
+        let recorder = AudioRecorder::new();
+        
+        // Frames before start and after stop are not recorded
+        recorder.write(&frame(1.0, 48000, 2, 100));
+        recorder.start(&path).unwrap();
+        assert!(recorder.is_recording());
+        recorder.write(&frame(0.25, 48000, 2, 480));
+        // A mono frame at half the rate is converted to the recording's format
+        recorder.write(&frame(-0.5, 24000, 1, 240));
+        assert_eq!(recorder.stop(), Some(path.clone()));
+        assert!(!recorder.is_recording());
+        recorder.write(&frame(1.0, 48000, 2, 100));
+        
+        let decoder = rodio::Decoder::try_from(File::open(&path).unwrap()).unwrap();
+        assert_eq!(decoder.sample_rate(), 48000);
+        assert_eq!(decoder.channels(), 2);
+        let samples: Vec<f32> = decoder.collect();
+        let _ = std::fs::remove_file(&path);
+        
+        assert!((samples.len() as i64 - 960 * 2).abs() <= 4, "recorded {} samples", samples.len());
+        assert!(samples[..960].iter().all(|&sample| sample == 0.25));
+        assert!(samples[samples.len() - 100..].iter().all(|&sample| sample == -0.5));
+    }
+}
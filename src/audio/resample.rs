@@ -0,0 +1,277 @@
+use std::f64::consts::PI;
+
+/// Input frames on each side of the interpolation point used by `SincResampler`
+const SINC_HALF_TAPS: usize = 32;
+
+/// Number of precomputed fractional positions of the sinc kernel
+const SINC_PHASES: usize = 512;
+
+/// Streaming linear-interpolation resampler for interleaved audio
+///
+/// Keeps the last input frame between calls, so a stream can be fed in blocks
+/// of any size without discontinuities at the block boundaries.
+pub struct Resampler {
+    from_rate: u32,
+    to_rate: u32,
+    channels: usize,
+    /// Position of the next output frame in input frames, relative to `previous`
+    position: f64,
+    /// Last input frame of the previous block
+    previous: Vec<f32>,
+}
+
+impl Resampler {
+    /// Create a resampler converting `channels` interleaved channels from `from_rate` to `to_rate`
+    pub fn new(from_rate: u32, to_rate: u32, channels: u16) -> Self {
+        let channels = channels.max(1) as usize;
+        Self {
+            from_rate: from_rate.max(1),
+            to_rate: to_rate.max(1),
+            channels,
+            // The first output frame lands on the first input frame
+            position: 1.0,
+            previous: vec![0.0; channels],
+        }
+    }
+    
+    /// Input sample rate
+    pub fn input_rate(&self) -> u32 {
+        self.from_rate
+    }
+    
+    /// Resample the next block of interleaved input
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.from_rate == self.to_rate {
+            return input.to_vec();
+        }
+        
+        let channels = self.channels;
+        let input_frames = input.len() / channels;
+        if input_frames == 0 {
+            return Vec::new();
+        }
+        
+        let step = self.from_rate as f64 / self.to_rate as f64;
+        let estimated = (input_frames as f64 / step).ceil() as usize + 1;
+        let mut output = Vec::with_capacity(estimated * channels);
+        
+        // Index 0 is the previous block's last frame, input frame i is index i + 1
+        let sample = |index: usize, channel: usize| -> f32 {
+            if index == 0 {
+                self.previous[channel]
+            } else {
+                input[(index - 1) * channels + channel]
+            }
+        };
+        
+        let mut position = self.position;
+        while position < input_frames as f64 {
+            let index = position as usize;
+            let fraction = (position - index as f64) as f32;
+            for channel in 0..channels {
+                let a = sample(index, channel);
+                let b = sample(index + 1, channel);
+                output.push(a + (b - a) * fraction);
+            }
+            position += step;
+        }
+        
+        self.position = position - input_frames as f64;
+        self.previous.copy_from_slice(&input[(input_frames - 1) * channels..input_frames * channels]);
+        
+        output
+    }
+}
+
+/// Streaming windowed-sinc resampler for interleaved audio
+///
+/// Band-limits the signal to the lower of the two Nyquist frequencies, so
+/// downsampling doesn't fold high frequencies back into the spectrum. Output
+/// lags the input by `SINC_HALF_TAPS` frames.
+pub struct SincResampler {
+    from_rate: u32,
+    to_rate: u32,
+    channels: usize,
+    /// Kernel rows for `SINC_PHASES + 1` fractional offsets, `2 * SINC_HALF_TAPS` taps each
+    kernel: Vec<f32>,
+    /// Buffered input frames, interleaved
+    history: Vec<f32>,
+    /// Position of the next output frame in frames from the start of `history`
+    position: f64,
+}
+
+impl SincResampler {
+    /// Create a resampler converting `channels` interleaved channels from `from_rate` to `to_rate`
+    pub fn new(from_rate: u32, to_rate: u32, channels: u16) -> Self {
+        let from_rate = from_rate.max(1);
+        let to_rate = to_rate.max(1);
+        
+        Self {
+            from_rate,
+            to_rate,
+            channels: channels.max(1) as usize,
+            kernel: Self::build_kernel((to_rate as f64 / from_rate as f64).min(1.0)),
+            history: Vec::new(),
+            position: 0.0,
+        }
+    }
+    
+    /// Blackman-windowed sinc low-pass with `cutoff` relative to the input Nyquist frequency
+    fn build_kernel(cutoff: f64) -> Vec<f32> {
+        let taps = 2 * SINC_HALF_TAPS;
+        let mut kernel = Vec::with_capacity((SINC_PHASES + 1) * taps);
+        
+        for phase in 0..=SINC_PHASES {
+            let fraction = phase as f64 / SINC_PHASES as f64;
+            let row: Vec<f64> = (0..taps)
+                .map(|tap| {
+                    let x = tap as f64 - (SINC_HALF_TAPS - 1) as f64 - fraction;
+                    let t = x / SINC_HALF_TAPS as f64;
+                    let window = if t.abs() >= 1.0 {
+                        0.0
+                    } else {
+                        0.42 + 0.5 * (PI * t).cos() + 0.08 * (2.0 * PI * t).cos()
+                    };
+                    let sinc = if x == 0.0 { 1.0 } else { (PI * cutoff * x).sin() / (PI * cutoff * x) };
+                    cutoff * sinc * window
+                })
+                .collect();
+            
+            // Unity gain at DC, so constant signals pass through unchanged
+            let sum: f64 = row.iter().sum();
+            kernel.extend(row.iter().map(|&h| (h / sum) as f32));
+        }
+        
+        kernel
+    }
+    
+    /// Input sample rate
+    pub fn input_rate(&self) -> u32 {
+        self.from_rate
+    }
+    
+    /// Resample the next block of interleaved input
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.from_rate == self.to_rate {
+            return input.to_vec();
+        }
+        
+        let channels = self.channels;
+        if input.len() < channels {
+            return Vec::new();
+        }
+        
+        // Extend the first frame backwards, so the output starts on the first input frame
+        if self.history.is_empty() {
+            for _ in 0..SINC_HALF_TAPS - 1 {
+                self.history.extend_from_slice(&input[..channels]);
+            }
+            self.position = (SINC_HALF_TAPS - 1) as f64;
+        }
+        self.history.extend_from_slice(&input[..input.len() / channels * channels]);
+        
+        let frames = self.history.len() / channels;
+        let step = self.from_rate as f64 / self.to_rate as f64;
+        let taps = 2 * SINC_HALF_TAPS;
+        let mut output = Vec::with_capacity(((input.len() / channels) as f64 / step) as usize * channels + channels);
+        
+        // The taps of the output frame at `position` cover frames index - HALF + 1 ..= index + HALF
+        while (self.position as usize) + SINC_HALF_TAPS < frames {
+            let index = self.position as usize;
+            let phase = ((self.position - index as f64) * SINC_PHASES as f64).round() as usize;
+            let row = &self.kernel[phase * taps..(phase + 1) * taps];
+            let first = index + 1 - SINC_HALF_TAPS;
+            
+            for channel in 0..channels {
+                let value: f32 = row.iter()
+                    .enumerate()
+                    .map(|(tap, h)| h * self.history[(first + tap) * channels + channel])
+                    .sum();
+                output.push(value);
+            }
+            
+            self.position += step;
+        }
+        
+        // Drop frames no future output frame can reach
+        let consumed = (self.position as usize + 1).saturating_sub(SINC_HALF_TAPS);
+        self.history.drain(..consumed * channels);
+        self.position -= consumed as f64;
+        
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_resample_is_continuous_across_blocks() {
+        let ramp: Vec<f32> = (0..64).map(|i| i as f32).collect();
+        
+        // Downsampling by 2 picks every other sample, whatever the block sizes
+        let mut down = Resampler::new(2, 1, 1);
+        let output: Vec<f32> = [5, 13, 1, 20, 25].iter()
+            .scan(0, |start, &len| {
+                let block = &ramp[*start..*start + len];
+                *start += len;
+                Some(down.process(block))
+            })
+            .flatten()
+            .collect();
+        let expected: Vec<f32> = (0..32).map(|i| (i * 2) as f32).collect();
+        assert_eq!(output, expected);
+        
+        // Upsampling by 2 interpolates halfway between samples
+        let mut up = Resampler::new(1, 2, 1);
+        let mut output = up.process(&ramp[..7]);
+        output.extend(up.process(&ramp[7..16]));
+        let expected: Vec<f32> = (0..30).map(|i| i as f32 * 0.5).collect();
+        assert_eq!(output, expected);
+    }
+    
+    #[test]
+    fn test_resample_length_and_channels() {
+        let mut resampler = Resampler::new(48000, 44100, 2);
+        let input: Vec<f32> = (0..4800).flat_map(|_| [0.25, -0.5]).collect();
+        
+        let output = resampler.process(&input);
+        assert_eq!(output.len() % 2, 0);
+        assert!((output.len() as i64 / 2 - 4410).abs() <= 1);
+        assert!(output.chunks(2).all(|frame| frame == [0.25, -0.5]));
+    }
+    
+    #[test]
+    fn test_sinc_preserves_constant_and_tone() {
+        let mut resampler = SincResampler::new(48000, 44100, 2);
+        let constant: Vec<f32> = (0..4800).flat_map(|_| [0.25, -0.5]).collect();
+        let output = resampler.process(&constant);
+        assert!(output.chunks(2).all(|frame| (frame[0] - 0.25).abs() < 1e-5 && (frame[1] + 0.5).abs() < 1e-5));
+        
+        // A 1 kHz tone comes out as the same tone at the new rate, starting on the first input frame
+        let mut resampler = SincResampler::new(48000, 44100, 1);
+        let tone: Vec<f32> = (0..48000)
+            .map(|n| (2.0 * PI * 1000.0 * n as f64 / 48000.0).sin() as f32)
+            .collect();
+        let output: Vec<f32> = tone.chunks(512).flat_map(|block| resampler.process(block)).collect();
+        assert!((output.len() as i64 - 44100).abs() <= SINC_HALF_TAPS as i64 + 1);
+        for (n, sample) in output.iter().enumerate().skip(SINC_HALF_TAPS) {
+            let expected = (2.0 * PI * 1000.0 * n as f64 / 44100.0).sin() as f32;
+            assert!((sample - expected).abs() < 2e-3, "sample {}: {} != {}", n, sample, expected);
+        }
+    }
+    
+    #[test]
+    fn test_sinc_rejects_aliases() {
+        // 40 kHz would fold down to 8 kHz at 48 kHz without the low-pass
+        let mut resampler = SincResampler::new(96000, 48000, 1);
+        let tone: Vec<f32> = (0..96000)
+            .map(|n| (2.0 * PI * 40000.0 * n as f64 / 96000.0).sin() as f32)
+            .collect();
+        let output = resampler.process(&tone);
+        
+        let rms = (output[1000..].iter().map(|x| x * x).sum::<f32>() / (output.len() - 1000) as f32).sqrt();
+        assert!(rms < 0.01, "alias rms {}", rms);
+    }
+}
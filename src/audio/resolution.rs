@@ -0,0 +1,114 @@
+use anyhow::{Context, Result};
+use num_complex::Complex;
+use realfft::{RealFftPlanner, RealToComplex};
+use std::ops::Range;
+use std::sync::Arc;
+
+use super::analysis::AudioAnalyzer;
+use super::AudioSample;
+use crate::config::{AudioConfig, SpectrumResolution};
+
+/// Where a bin of the main spectrum takes its magnitude from
+enum BinSource {
+    /// The loudest of the finer bins it spans, so narrow peaks between main bins aren't lost
+    Peak(Range<usize>),
+    /// Interpolated between a coarser bin and the next, by the fraction
+    Interpolate(usize, f32),
+}
+
+/// An extra analysis at another FFT size, merged into the main spectrum over its frequency range
+///
+/// Magnitudes are calibrated to the main analysis, so a tone reads the same
+/// whichever analysis its bins come from. Longer windows react later: their
+/// centre lags the newest sample by half the window.
+pub struct ResolutionAnalyzer {
+    fft_processor: Arc<dyn RealToComplex<f32>>,
+    window: Vec<f32>,
+    /// Mono, left and right windows, shifted along one hop at a time
+    buffers: [Vec<f32>; 3],
+    fft_input: Vec<f32>,
+    fft_output: Vec<Complex<f32>>,
+    /// Converts this FFT's magnitudes to the main spectrum's
+    scale: f32,
+    /// Main spectrum bins this analysis replaces, and where each comes from
+    sources: Vec<(usize, BinSource)>,
+}
+
+impl ResolutionAnalyzer {
+    /// Create the analysis for `resolution`, merging into spectra with the given bin frequencies
+    pub fn new(
+        resolution: &SpectrumResolution,
+        config: &AudioConfig,
+        planner: &mut RealFftPlanner<f32>,
+        bin_frequencies: &[f32],
+    ) -> Self {
+        let window_size = resolution.window_size.min(resolution.fft_size);
+        let window = config.window_function.coefficients(window_size);
+        
+        // Both windows have the same coherent gain, so only their lengths and FFT sizes differ
+        let main_window_size = config.window_size.min(config.fft_size);
+        let main_gain: f32 = config.window_function.coefficients(main_window_size).iter().sum();
+        let gain: f32 = window.iter().sum();
+        let scale = if gain > 0.0 { main_gain / (gain * config.fft_size as f32) } else { 0.0 };
+        
+        let sample_rate = config.sample_rate as f32;
+        let main_spacing = sample_rate / config.fft_size as f32;
+        let spacing = sample_rate / resolution.fft_size as f32;
+        let last_bin = resolution.fft_size / 2;
+        let sources = bin_frequencies
+            .iter()
+            .enumerate()
+            .filter(|&(_, &frequency)| frequency >= resolution.min_hz && frequency < resolution.max_hz)
+            .map(|(bin, &frequency)| {
+                let source = if spacing < main_spacing {
+                    let start = ((frequency - main_spacing / 2.0) / spacing).ceil().max(0.0) as usize;
+                    let end = ((frequency + main_spacing / 2.0) / spacing).ceil() as usize;
+                    BinSource::Peak(start.min(last_bin)..end.min(last_bin + 1))
+                } else {
+                    let position = frequency / spacing;
+                    let lower = (position.floor() as usize).min(last_bin);
+                    BinSource::Interpolate(lower, position - lower as f32)
+                };
+                (bin, source)
+            })
+            .collect();
+        
+        Self {
+            fft_processor: planner.plan_fft_forward(resolution.fft_size),
+            window,
+            buffers: std::array::from_fn(|_| vec![0.0; window_size]),
+            fft_input: vec![0.0; resolution.fft_size],
+            fft_output: vec![Complex::new(0.0, 0.0); last_bin + 1],
+            scale,
+            sources,
+        }
+    }
+    
+    /// Shift a hop of mono, left and right samples into the windows
+    pub fn push(&mut self, hop: [&[AudioSample]; 3]) {
+        for (buffer, samples) in self.buffers.iter_mut().zip(hop) {
+            AudioAnalyzer::update_input_buffer(buffer, samples);
+        }
+    }
+    
+    /// Transform one channel's window (0 mono, 1 left, 2 right) and write it over its bins of `bins`
+    pub fn merge(&mut self, channel: usize, bins: &mut [f32]) -> Result<()> {
+        AudioAnalyzer::prepare_fft_input(&self.buffers[channel], &self.window, &mut self.fft_input);
+        self.fft_processor.process(&mut self.fft_input, &mut self.fft_output)
+            .context("FFT processing failed")?;
+        
+        let magnitude = |bin: usize| self.fft_output.get(bin).map_or(0.0, |c| c.norm() * self.scale);
+        for (bin, source) in &self.sources {
+            let Some(target) = bins.get_mut(*bin) else {
+                continue;
+            };
+            *target = match source {
+                BinSource::Peak(range) => range.clone().map(magnitude).fold(0.0, f32::max),
+                BinSource::Interpolate(lower, fraction) => {
+                    magnitude(*lower) * (1.0 - fraction) + magnitude(lower + 1) * fraction
+                }
+            };
+        }
+        Ok(())
+    }
+}
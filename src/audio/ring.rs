@@ -0,0 +1,272 @@
+use crossbeam_channel::{Receiver, SendError, SendTimeoutError, Sender, TrySendError};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+/// How often a blocked `send_blocking` checks whether the receiver went away
+const BLOCKING_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Snapshot of a ring channel's counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Items waiting to be received
+    pub depth: usize,
+    
+    /// Maximum number of queued items
+    pub capacity: usize,
+    
+    /// Number of times the queue filled up and started dropping items
+    pub overruns: u64,
+    
+    /// Items dropped to make room for newer ones
+    pub dropped: u64,
+}
+
+/// Sees each item a sender queues, before it can be dropped
+type Tap<T> = Arc<dyn Fn(&T) + Send + Sync>;
+
+/// Counters shared by both ends of a ring channel
+#[derive(Default)]
+struct RingCounters {
+    overruns: AtomicU64,
+    dropped: AtomicU64,
+    overflowing: AtomicBool,
+}
+
+/// Sending half of a bounded channel that drops the oldest item when full
+///
+/// `send` never blocks and only uses the lock-free operations of the underlying
+/// bounded channel, so it is safe to call from a real-time audio callback.
+/// A tap added with `with_tap` runs inside `send` as well, so it has to be
+/// just as safe.
+pub struct RingSender<T> {
+    sender: Sender<T>,
+    // Used to pop the oldest item when the ring is full
+    oldest: Receiver<T>,
+    counters: Arc<RingCounters>,
+    // Alive as long as any `RingReceiver` is
+    receiver_alive: Weak<()>,
+    tap: Option<Tap<T>>,
+}
+
+/// Receiving half of a ring channel
+///
+/// Dereferences to the underlying `crossbeam_channel::Receiver`, so it can be
+/// used with `recv`, `recv_timeout`, `Select` and friends.
+pub struct RingReceiver<T> {
+    receiver: Receiver<T>,
+    counters: Arc<RingCounters>,
+    _alive: Arc<()>,
+}
+
+/// Create a ring channel holding at most `capacity` items
+pub fn ring_channel<T>(capacity: usize) -> (RingSender<T>, RingReceiver<T>) {
+    let (sender, receiver) = crossbeam_channel::bounded(capacity.max(1));
+    let counters = Arc::new(RingCounters::default());
+    let alive = Arc::new(());
+    
+    let ring_sender = RingSender {
+        sender,
+        oldest: receiver.clone(),
+        counters: Arc::clone(&counters),
+        receiver_alive: Arc::downgrade(&alive),
+        tap: None,
+    };
+    let ring_receiver = RingReceiver {
+        receiver,
+        counters,
+        _alive: alive,
+    };
+    
+    (ring_sender, ring_receiver)
+}
+
+fn stats<T>(receiver: &Receiver<T>, counters: &RingCounters) -> QueueStats {
+    QueueStats {
+        depth: receiver.len(),
+        capacity: receiver.capacity().unwrap_or(0),
+        overruns: counters.overruns.load(Ordering::Relaxed),
+        dropped: counters.dropped.load(Ordering::Relaxed),
+    }
+}
+
+impl<T> RingSender<T> {
+    /// Show every item sent through this sender and its clones to `tap` first
+    ///
+    /// The tap sees items the ring later drops, e.g. to record everything a
+    /// source produced even when the receiver falls behind.
+    pub fn with_tap(mut self, tap: impl Fn(&T) + Send + Sync + 'static) -> Self {
+        self.tap = Some(Arc::new(tap));
+        self
+    }
+    
+    /// Queue `item`, dropping the oldest queued items if the ring is full
+    ///
+    /// Fails only once every receiver is gone.
+    pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+        if self.receiver_alive.strong_count() == 0 {
+            return Err(SendError(item));
+        }
+        if let Some(tap) = &self.tap {
+            tap(&item);
+        }
+        
+        let mut item = item;
+        let mut dropped = false;
+        loop {
+            match self.sender.try_send(item) {
+                Ok(()) => break,
+                Err(TrySendError::Full(rejected)) => {
+                    item = rejected;
+                    if self.oldest.try_recv().is_ok() {
+                        self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                        dropped = true;
+                    }
+                }
+                Err(TrySendError::Disconnected(rejected)) => return Err(SendError(rejected)),
+            }
+        }
+        
+        // Count each run of drops as one overrun
+        let was_overflowing = self.counters.overflowing.swap(dropped, Ordering::Relaxed);
+        if dropped && !was_overflowing {
+            self.counters.overruns.fetch_add(1, Ordering::Relaxed);
+        }
+        
+        Ok(())
+    }
+    
+    /// Queue `item`, waiting for room instead of dropping anything
+    ///
+    /// For producers that are not real-time and can be slowed down, such as a pipe.
+    pub fn send_blocking(&self, item: T) -> Result<(), SendError<T>> {
+        if let Some(tap) = &self.tap {
+            tap(&item);
+        }
+        
+        let mut item = item;
+        loop {
+            if self.receiver_alive.strong_count() == 0 {
+                return Err(SendError(item));
+            }
+            
+            match self.sender.send_timeout(item, BLOCKING_POLL_INTERVAL) {
+                Ok(()) => return Ok(()),
+                Err(SendTimeoutError::Timeout(rejected)) => item = rejected,
+                Err(SendTimeoutError::Disconnected(rejected)) => return Err(SendError(rejected)),
+            }
+        }
+    }
+    
+    /// Current counters of the channel
+    pub fn stats(&self) -> QueueStats {
+        stats(&self.oldest, &self.counters)
+    }
+}
+
+impl<T> Clone for RingSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            oldest: self.oldest.clone(),
+            counters: Arc::clone(&self.counters),
+            receiver_alive: Weak::clone(&self.receiver_alive),
+            tap: self.tap.clone(),
+        }
+    }
+}
+
+impl<T> RingReceiver<T> {
+    /// Current counters of the channel
+    pub fn stats(&self) -> QueueStats {
+        stats(&self.receiver, &self.counters)
+    }
+}
+
+impl<T> Deref for RingReceiver<T> {
+    type Target = Receiver<T>;
+    
+    fn deref(&self) -> &Receiver<T> {
+        &self.receiver
+    }
+}
+
+impl<T> Clone for RingReceiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            receiver: self.receiver.clone(),
+            counters: Arc::clone(&self.counters),
+            _alive: Arc::clone(&self._alive),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_ring_drops_oldest() {
+        let (sender, receiver) = ring_channel(3);
+        for i in 0..5 {
+            sender.send(i).unwrap();
+        }
+        
+        assert_eq!(receiver.stats(), QueueStats { depth: 3, capacity: 3, overruns: 1, dropped: 2 });
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![2, 3, 4]);
+        
+        // Draining ends the overrun, the next overflow counts as a new one
+        sender.send(5).unwrap();
+        for i in 6..9 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(sender.stats().overruns, 2);
+        assert_eq!(sender.stats().dropped, 3);
+    }
+    
+    #[test]
+    fn test_ring_tap_sees_dropped_items() {
+        let seen = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let (sender, receiver) = ring_channel(2);
+        let sender = {
+            let seen = Arc::clone(&seen);
+            sender.with_tap(move |&item| seen.lock().push(item))
+        };
+        
+        for i in 0..3 {
+            sender.clone().send(i).unwrap();
+        }
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+        sender.send_blocking(3).unwrap();
+        
+        assert_eq!(*seen.lock(), vec![0, 1, 2, 3]);
+    }
+    
+    #[test]
+    fn test_ring_detects_dropped_receiver() {
+        let (sender, receiver) = ring_channel(2);
+        let clone = receiver.clone();
+        drop(receiver);
+        assert!(sender.send(1).is_ok());
+        
+        drop(clone);
+        assert!(sender.send(2).is_err());
+        assert!(sender.send_blocking(3).is_err());
+    }
+    
+    #[test]
+    fn test_ring_blocking_send_waits_for_room() {
+        let (sender, receiver) = ring_channel(1);
+        sender.send_blocking(1).unwrap();
+        
+        let consumer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            receiver.iter().take(2).collect::<Vec<_>>()
+        });
+        sender.send_blocking(2).unwrap();
+        
+        assert_eq!(consumer.join().unwrap(), vec![1, 2]);
+        assert_eq!(sender.stats().dropped, 0);
+    }
+}
@@ -0,0 +1,142 @@
+use anyhow::Result;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::capture::CpalSource;
+use super::file::FileSource;
+use super::mixer::MixerSource;
+use super::network::NetworkSource;
+use super::stdin::StdinSource;
+use super::synthetic::SyntheticSource;
+use super::ring::RingSender;
+use super::AudioFrame;
+use crate::config::{AudioCaptureMode, AudioConfig};
+
+/// Sample format delivered by an audio source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} Hz, {} channels", self.sample_rate, self.channels)
+    }
+}
+
+/// A producer of audio frames (device, file, generator, pipe...)
+///
+/// Sources push interleaved `AudioFrame`s into the sender they are started with
+/// and must stop doing so once `stop` returns. Real-time sources use `send`,
+/// which drops the oldest queued frame instead of blocking when the consumer
+/// falls behind. Sources that can be slowed down use `send_blocking`.
+pub trait AudioSource: Send {
+    /// Start producing frames into `sender`
+    fn start(&mut self, sender: RingSender<AudioFrame>) -> Result<()>;
+    
+    /// Stop producing frames and release the underlying resources
+    fn stop(&mut self) -> Result<()>;
+    
+    /// Human readable description of the source
+    fn describe(&self) -> String;
+    
+    /// Format of the produced frames, if known (usually only after `start`)
+    fn format(&self) -> Option<AudioFormat>;
+    
+    /// Whether the source is still delivering audio (false once e.g. its device was unplugged)
+    fn is_healthy(&self) -> bool {
+        true
+    }
+    
+    /// Name of the device the source was configured to capture from, if any
+    fn device_name(&self) -> Option<String> {
+        None
+    }
+    
+    /// Create an unstarted source of the same kind capturing from another device
+    fn with_device(&self, device_name: Option<String>) -> Result<Box<dyn AudioSource>> {
+        let _ = device_name;
+        anyhow::bail!("{} does not support switching devices", self.describe())
+    }
+}
+
+/// Create the audio source selected by the configuration
+pub fn create_source(config: &AudioConfig) -> Result<Box<dyn AudioSource>> {
+    let source: Box<dyn AudioSource> = match config.capture_mode {
+        AudioCaptureMode::File => {
+            let path = config.input_file.clone()
+                .ok_or_else(|| anyhow::anyhow!("File capture mode requires an input file"))?;
+            Box::new(FileSource::new(path, config.buffer_size, config.file_playback_output))
+        }
+        AudioCaptureMode::Synthetic => {
+            let format = AudioFormat {
+                sample_rate: config.sample_rate,
+                channels: 2,
+            };
+            Box::new(SyntheticSource::new(
+                config.test_signal.clone(),
+                config.test_signal_amplitude,
+                format,
+                config.buffer_size,
+            ))
+        }
+        AudioCaptureMode::Pcm => match config.pcm_path.clone() {
+            Some(path) => Box::new(StdinSource::from_path(path, config.pcm_format, config.buffer_size)),
+            None => Box::new(StdinSource::new(config.pcm_format, config.buffer_size)),
+        },
+        AudioCaptureMode::Network => Box::new(NetworkSource::new(
+            config.network_address,
+            config.network_payload,
+            config.pcm_format,
+            config.network_jitter_ms,
+            config.buffer_size,
+        )),
+        AudioCaptureMode::Both => {
+            // One stream per device, mixed into a common stereo stream
+            let input = AudioConfig { capture_mode: AudioCaptureMode::Input, ..config.clone() };
+            let loopback = AudioConfig { capture_mode: AudioCaptureMode::Loopback, ..config.clone() };
+            let format = AudioFormat {
+                sample_rate: config.sample_rate,
+                channels: 2,
+            };
+            Box::new(MixerSource::new(format, config.buffer_size)
+                .with_device_source(Box::new(CpalSource::new(&input)), config.input_gain)
+                .with_source(Box::new(CpalSource::new(&loopback)), config.loopback_gain))
+        }
+        AudioCaptureMode::Input | AudioCaptureMode::Loopback => {
+            Box::new(CpalSource::new(config))
+        }
+    };
+    
+    Ok(source)
+}
+
+/// Paces a producer thread so frames are emitted in real time
+pub(crate) struct RealtimePacer {
+    start_time: Instant,
+    frames_sent: u64,
+    sample_rate: u32,
+}
+
+impl RealtimePacer {
+    pub(crate) fn new(sample_rate: u32) -> Self {
+        Self {
+            start_time: Instant::now(),
+            frames_sent: 0,
+            sample_rate: sample_rate.max(1),
+        }
+    }
+    
+    /// Account for `frames` sent and sleep until the wall clock catches up
+    pub(crate) fn wait(&mut self, frames: usize) {
+        self.frames_sent += frames as u64;
+        
+        let due = self.start_time
+            + Duration::from_secs_f64(self.frames_sent as f64 / self.sample_rate as f64);
+        let now = Instant::now();
+        if due > now {
+            std::thread::sleep(due - now);
+        }
+    }
+}
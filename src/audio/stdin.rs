@@ -0,0 +1,274 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+use super::source::{AudioFormat, AudioSource};
+use super::ring::RingSender;
+use super::AudioFrame;
+use crate::config::{PcmEncoding, PcmFormat};
+
+/// Where raw PCM is read from
+enum PcmInput {
+    /// The process's standard input
+    Stdin,
+    /// A named pipe or regular file, opened on the reader thread
+    Path(PathBuf),
+    /// An arbitrary reader (used by tests)
+    #[cfg(test)]
+    Reader(Box<dyn Read + Send>),
+}
+
+/// Audio source reading interleaved raw PCM from stdin or a named pipe
+///
+/// The producer on the other end of the pipe sets the pace, so frames are
+/// emitted as soon as a full buffer has been read.
+pub struct StdinSource {
+    input: Option<PcmInput>,
+    description: String,
+    pcm_format: PcmFormat,
+    buffer_size: usize,
+    is_running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StdinSource {
+    /// Read PCM in `pcm_format` from the process's standard input
+    pub fn new(pcm_format: PcmFormat, buffer_size: usize) -> Self {
+        Self::with_input(PcmInput::Stdin, "stdin".to_string(), pcm_format, buffer_size)
+    }
+    
+    /// Read PCM in `pcm_format` from a named pipe (e.g. mpd's fifo output) or file
+    pub fn from_path(path: PathBuf, pcm_format: PcmFormat, buffer_size: usize) -> Self {
+        let description = path.display().to_string();
+        Self::with_input(PcmInput::Path(path), description, pcm_format, buffer_size)
+    }
+    
+    /// Read PCM in `pcm_format` from an arbitrary reader
+    #[cfg(test)]
+    pub fn from_reader(
+        reader: Box<dyn Read + Send>,
+        description: &str,
+        pcm_format: PcmFormat,
+        buffer_size: usize,
+    ) -> Self {
+        Self::with_input(PcmInput::Reader(reader), description.to_string(), pcm_format, buffer_size)
+    }
+    
+    fn with_input(input: PcmInput, description: String, pcm_format: PcmFormat, buffer_size: usize) -> Self {
+        Self {
+            input: Some(input),
+            description,
+            pcm_format,
+            buffer_size: buffer_size.max(1),
+            is_running: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+    
+    /// Read loop, runs until end of stream, a read error or stop
+    fn read_loop(
+        input: PcmInput,
+        pcm_format: PcmFormat,
+        buffer_size: usize,
+        sender: RingSender<AudioFrame>,
+        is_running: Arc<AtomicBool>,
+    ) {
+        let frame_bytes = pcm_format.encoding.bytes_per_sample() * pcm_format.channels.max(1) as usize;
+        let mut bytes = vec![0u8; buffer_size * frame_bytes];
+        
+        let (mut reader, reopen_path) = match input {
+            PcmInput::Stdin => (Some(Box::new(std::io::stdin()) as Box<dyn Read + Send>), None),
+            #[cfg(test)]
+            PcmInput::Reader(reader) => (Some(reader), None),
+            PcmInput::Path(path) => (None, Some(path)),
+        };
+        
+        while is_running.load(Ordering::Relaxed) {
+            // Opening a FIFO blocks until a writer connects
+            if reader.is_none() {
+                let Some(path) = &reopen_path else { break };
+                match File::open(path) {
+                    Ok(file) => {
+                        log::info!("Reading PCM input from {}", path.display());
+                        reader = Some(Box::new(file));
+                    }
+                    Err(e) => {
+                        log::error!("Failed to open PCM input {}: {}", path.display(), e);
+                        break;
+                    }
+                }
+            }
+            
+            let Some(current) = reader.as_mut() else { break };
+            
+            // A short read at end of stream drops the incomplete tail
+            if let Err(e) = current.read_exact(&mut bytes) {
+                if e.kind() != std::io::ErrorKind::UnexpectedEof {
+                    log::error!("Failed to read PCM input: {}", e);
+                    break;
+                }
+                
+                // A FIFO reaches end of stream whenever its writer goes away, wait for the next one
+                match &reopen_path {
+                    Some(path) if Self::is_fifo(path) => {
+                        log::info!("PCM writer disconnected from {}, waiting for a new one", path.display());
+                        reader = None;
+                        continue;
+                    }
+                    _ => break,
+                }
+            }
+            
+            let frame = AudioFrame {
+                samples: decode_pcm(&bytes, pcm_format.encoding),
+                timestamp: Instant::now(),
+                sample_rate: pcm_format.sample_rate,
+                channels: pcm_format.channels,
+            };
+            
+            // Pipes can wait, so apply back-pressure instead of dropping audio
+            if sender.send_blocking(frame).is_err() {
+                break;
+            }
+        }
+        
+        is_running.store(false, Ordering::Relaxed);
+    }
+    
+    /// Check whether `path` is a named pipe
+    #[cfg(unix)]
+    fn is_fifo(path: &std::path::Path) -> bool {
+        use std::os::unix::fs::FileTypeExt;
+        std::fs::metadata(path).map(|m| m.file_type().is_fifo()).unwrap_or(false)
+    }
+    
+    /// Check whether `path` is a named pipe
+    #[cfg(not(unix))]
+    fn is_fifo(_path: &std::path::Path) -> bool {
+        false
+    }
+}
+
+/// Convert raw PCM bytes to normalized f32 samples
+pub fn decode_pcm(bytes: &[u8], encoding: PcmEncoding) -> Vec<f32> {
+    match encoding {
+        PcmEncoding::F32Le => bytes.chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        PcmEncoding::F32Be => bytes.chunks_exact(4)
+            .map(|b| f32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        PcmEncoding::S16Le => bytes.chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+            .collect(),
+        PcmEncoding::S16Be => bytes.chunks_exact(2)
+            .map(|b| i16::from_be_bytes([b[0], b[1]]) as f32 / 32768.0)
+            .collect(),
+        PcmEncoding::S32Le => bytes.chunks_exact(4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2147483648.0)
+            .collect(),
+        PcmEncoding::S32Be => bytes.chunks_exact(4)
+            .map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2147483648.0)
+            .collect(),
+        PcmEncoding::U8 => bytes.iter()
+            .map(|&b| (b as f32 - 128.0) / 128.0)
+            .collect(),
+    }
+}
+
+impl AudioSource for StdinSource {
+    fn start(&mut self, sender: RingSender<AudioFrame>) -> Result<()> {
+        let input = self.input.take()
+            .ok_or_else(|| anyhow::anyhow!("PCM input {} can only be started once", self.description))?;
+        
+        self.is_running.store(true, Ordering::Relaxed);
+        let thread = {
+            let pcm_format = self.pcm_format;
+            let buffer_size = self.buffer_size;
+            let is_running = Arc::clone(&self.is_running);
+            std::thread::Builder::new()
+                .name("audio-pcm-input".to_string())
+                .spawn(move || {
+                    Self::read_loop(input, pcm_format, buffer_size, sender, is_running);
+                })
+                .context("Failed to spawn PCM input thread")?
+        };
+        
+        self.thread = Some(thread);
+        Ok(())
+    }
+    
+    fn stop(&mut self) -> Result<()> {
+        self.is_running.store(false, Ordering::Relaxed);
+        
+        // The reader may be blocked waiting for input that never comes, so the
+        // thread is detached rather than joined. It exits on its next read.
+        self.thread.take();
+        
+        Ok(())
+    }
+    
+    fn describe(&self) -> String {
+        format!("PCM input: {}", self.description)
+    }
+    
+    fn format(&self) -> Option<AudioFormat> {
+        Some(AudioFormat {
+            sample_rate: self.pcm_format.sample_rate,
+            channels: self.pcm_format.channels,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::ring::ring_channel;
+    
+    #[test]
+    fn test_read_pcm_frames() {
+        let samples: Vec<f32> = (0..16).map(|i| i as f32 / 16.0).collect();
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        
+        let pcm_format: PcmFormat = "f32le:48000:2".parse().unwrap();
+        let mut source = StdinSource::from_reader(Box::new(std::io::Cursor::new(bytes)), "test", pcm_format, 4);
+        
+        let (sender, receiver) = ring_channel(1);
+        source.start(sender).unwrap();
+        
+        let frames: Vec<AudioFrame> = receiver.iter().collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].samples, samples[..8]);
+        assert_eq!(frames[1].samples, samples[8..]);
+        assert_eq!(frames[0].channels, 2);
+        assert_eq!(frames[0].sample_rate, 48000);
+    }
+    
+    #[test]
+    fn test_decode_integer_pcm() {
+        let s16: Vec<u8> = [i16::MIN, 0, 16384].iter().flat_map(|s| s.to_le_bytes()).collect();
+        assert_eq!(decode_pcm(&s16, PcmEncoding::S16Le), vec![-1.0, 0.0, 0.5]);
+        
+        let s16_be: Vec<u8> = [16384i16].iter().flat_map(|s| s.to_be_bytes()).collect();
+        assert_eq!(decode_pcm(&s16_be, PcmEncoding::S16Be), vec![0.5]);
+        
+        assert_eq!(decode_pcm(&[0, 128, 192], PcmEncoding::U8), vec![-1.0, 0.0, 0.5]);
+    }
+    
+    #[test]
+    fn test_parse_pcm_format() {
+        let format: PcmFormat = "s16le:44100:1".parse().unwrap();
+        assert_eq!(format.encoding, PcmEncoding::S16Le);
+        assert_eq!(format.sample_rate, 44100);
+        assert_eq!(format.channels, 1);
+        
+        assert!("f32le:48000".parse::<PcmFormat>().is_err());
+        assert!("pcm:48000:2".parse::<PcmFormat>().is_err());
+        assert!("f32le:48000:0".parse::<PcmFormat>().is_err());
+    }
+}
@@ -0,0 +1,317 @@
+use anyhow::{Context, Result};
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use super::source::{AudioFormat, AudioSource, RealtimePacer};
+use super::ring::RingSender;
+use super::AudioFrame;
+use crate::config::TestSignal;
+
+/// Kick drum envelope decay time constant (seconds)
+const KICK_DECAY: f64 = 0.04;
+
+/// Kick drum pitch drop time constant (seconds)
+const KICK_PITCH_DECAY: f64 = 0.03;
+
+/// Deterministic generator for the built-in test signals
+pub struct SignalGenerator {
+    signal: TestSignal,
+    amplitude: f32,
+    sample_rate: u32,
+    position: u64,
+    phase: f64,
+    rng_state: u64,
+    pink_state: [f32; 7],
+}
+
+impl SignalGenerator {
+    /// Create a generator for `signal` with the given peak amplitude
+    pub fn new(signal: TestSignal, amplitude: f32, sample_rate: u32) -> Self {
+        Self {
+            signal,
+            amplitude,
+            sample_rate: sample_rate.max(1),
+            position: 0,
+            phase: 0.0,
+            rng_state: 0x9E37_79B9_7F4A_7C15,
+            pink_state: [0.0; 7],
+        }
+    }
+    
+    /// Number of samples generated so far
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+    
+    /// Fill `output` with the next mono samples of the signal
+    pub fn fill(&mut self, output: &mut [f32]) {
+        for sample in output.iter_mut() {
+            *sample = self.amplitude * self.next_sample();
+            self.position += 1;
+        }
+    }
+    
+    /// Generate the next sample at unit amplitude
+    fn next_sample(&mut self) -> f32 {
+        let sample_rate = self.sample_rate as f64;
+        let t = self.position as f64 / sample_rate;
+        
+        match self.signal {
+            TestSignal::Sine { frequency } => {
+                let value = self.phase.sin() as f32;
+                self.advance_phase(frequency as f64);
+                value
+            }
+            TestSignal::Sweep { start_frequency, end_frequency, duration } => {
+                // Exponential sweep so every octave gets the same amount of time
+                let duration = (duration as f64).max(0.1);
+                let progress = (t % duration) / duration;
+                let start = (start_frequency as f64).max(1.0);
+                let end = (end_frequency as f64).max(1.0);
+                let frequency = start * (end / start).powf(progress);
+                
+                let value = self.phase.sin() as f32;
+                self.advance_phase(frequency);
+                value
+            }
+            TestSignal::WhiteNoise => self.next_random(),
+            TestSignal::PinkNoise => {
+                // Paul Kellet's refined pink noise filter
+                let white = self.next_random();
+                let b = &mut self.pink_state;
+                b[0] = 0.99886 * b[0] + white * 0.0555179;
+                b[1] = 0.99332 * b[1] + white * 0.0750759;
+                b[2] = 0.96900 * b[2] + white * 0.153852;
+                b[3] = 0.86650 * b[3] + white * 0.3104856;
+                b[4] = 0.55000 * b[4] + white * 0.5329522;
+                b[5] = -0.7616 * b[5] - white * 0.0168980;
+                let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+                b[6] = white * 0.115926;
+                (pink * 0.11).clamp(-1.0, 1.0)
+            }
+            TestSignal::Kick { bpm } => {
+                // Decaying sine whose pitch drops from 150 Hz to 50 Hz after every beat
+                let beat_length = 60.0 / (bpm as f64).max(1.0);
+                let since_beat = t % beat_length;
+                let phase = 2.0 * PI * (50.0 * since_beat
+                    + 100.0 * KICK_PITCH_DECAY * (1.0 - (-since_beat / KICK_PITCH_DECAY).exp()));
+                ((-since_beat / KICK_DECAY).exp() * phase.sin()) as f32
+            }
+            TestSignal::Silence => 0.0,
+        }
+    }
+    
+    /// Advance the oscillator phase by one sample at `frequency`
+    fn advance_phase(&mut self, frequency: f64) {
+        self.phase += 2.0 * PI * frequency / self.sample_rate as f64;
+        if self.phase > 2.0 * PI {
+            self.phase -= 2.0 * PI;
+        }
+    }
+    
+    /// Uniform random value in [-1, 1] (xorshift64*)
+    fn next_random(&mut self) -> f32 {
+        self.rng_state ^= self.rng_state >> 12;
+        self.rng_state ^= self.rng_state << 25;
+        self.rng_state ^= self.rng_state >> 27;
+        let value = self.rng_state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 40;
+        (value as f32 / (1u64 << 24) as f32) * 2.0 - 1.0
+    }
+}
+
+/// Audio source generating a known test signal
+pub struct SyntheticSource {
+    signal: TestSignal,
+    amplitude: f32,
+    format: AudioFormat,
+    buffer_size: usize,
+    realtime: bool,
+    is_running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SyntheticSource {
+    /// Create a generator emitting `signal` in frames of `buffer_size` samples per channel
+    pub fn new(signal: TestSignal, amplitude: f32, format: AudioFormat, buffer_size: usize) -> Self {
+        Self {
+            signal,
+            amplitude,
+            format,
+            buffer_size: buffer_size.max(1),
+            realtime: true,
+            is_running: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+    
+    /// Generate frames as fast as the receiver takes them instead of in real time
+    #[cfg(test)]
+    pub fn unpaced(mut self) -> Self {
+        self.realtime = false;
+        self
+    }
+    
+    /// Generator loop, runs until stopped or the receiver goes away
+    fn generate_loop(
+        mut generator: SignalGenerator,
+        format: AudioFormat,
+        buffer_size: usize,
+        realtime: bool,
+        sender: RingSender<AudioFrame>,
+        is_running: Arc<AtomicBool>,
+    ) {
+        let channels = format.channels.max(1) as usize;
+        let mut mono = vec![0.0; buffer_size];
+        let mut pacer = RealtimePacer::new(format.sample_rate);
+        let start_time = Instant::now();
+        
+        while is_running.load(Ordering::Relaxed) {
+            // Timestamps follow the signal clock, so unpaced runs still look like real time
+            let timestamp = start_time
+                + Duration::from_secs_f64(generator.position() as f64 / format.sample_rate as f64);
+            generator.fill(&mut mono);
+            
+            // Same signal on every channel
+            let samples = mono.iter()
+                .flat_map(|&sample| std::iter::repeat_n(sample, channels))
+                .collect();
+            
+            let frame = AudioFrame {
+                samples,
+                timestamp,
+                sample_rate: format.sample_rate,
+                channels: format.channels,
+            };
+            
+            let sent = if realtime { sender.send(frame) } else { sender.send_blocking(frame) };
+            if sent.is_err() {
+                break;
+            }
+            
+            if realtime {
+                pacer.wait(buffer_size);
+            }
+        }
+    }
+}
+
+impl AudioSource for SyntheticSource {
+    fn start(&mut self, sender: RingSender<AudioFrame>) -> Result<()> {
+        self.stop()?;
+        
+        self.is_running.store(true, Ordering::Relaxed);
+        let thread = {
+            let generator = SignalGenerator::new(self.signal.clone(), self.amplitude, self.format.sample_rate);
+            let format = self.format;
+            let buffer_size = self.buffer_size;
+            let realtime = self.realtime;
+            let is_running = Arc::clone(&self.is_running);
+            std::thread::Builder::new()
+                .name("audio-synthetic".to_string())
+                .spawn(move || {
+                    Self::generate_loop(generator, format, buffer_size, realtime, sender, is_running);
+                })
+                .context("Failed to spawn signal generator thread")?
+        };
+        
+        self.thread = Some(thread);
+        Ok(())
+    }
+    
+    fn stop(&mut self) -> Result<()> {
+        self.is_running.store(false, Ordering::Relaxed);
+        
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Signal generator thread panicked");
+            }
+        }
+        
+        Ok(())
+    }
+    
+    fn describe(&self) -> String {
+        format!("Synthetic: {:?}", self.signal)
+    }
+    
+    fn format(&self) -> Option<AudioFormat> {
+        Some(self.format)
+    }
+}
+
+impl Drop for SyntheticSource {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::ring::ring_channel;
+    
+    #[test]
+    fn test_sine_generation() {
+        let mut generator = SignalGenerator::new(TestSignal::Sine { frequency: 1000.0 }, 0.5, 48000);
+        let mut output = vec![0.0; 48];
+        generator.fill(&mut output);
+        
+        // 1 kHz at 48 kHz is 48 samples per period
+        assert!(output[0].abs() < 1e-6);
+        assert!((output[12] - 0.5).abs() < 1e-4);
+        assert!((output[36] + 0.5).abs() < 1e-4);
+    }
+    
+    #[test]
+    fn test_noise_is_bounded_and_zero_mean() {
+        for signal in [TestSignal::WhiteNoise, TestSignal::PinkNoise] {
+            let mut generator = SignalGenerator::new(signal, 1.0, 44100);
+            let mut output = vec![0.0; 44100];
+            generator.fill(&mut output);
+            
+            let mean = output.iter().sum::<f32>() / output.len() as f32;
+            assert!(output.iter().all(|sample| sample.abs() <= 1.0));
+            assert!(mean.abs() < 0.05);
+            assert!(output.iter().any(|sample| sample.abs() > 0.1));
+        }
+    }
+    
+    #[test]
+    fn test_kick_beats_land_on_tempo() {
+        let mut generator = SignalGenerator::new(TestSignal::Kick { bpm: 120.0 }, 1.0, 1000);
+        let mut output = vec![0.0; 2000];
+        generator.fill(&mut output);
+        
+        // Energy peaks right after each beat (every 500 samples) and has decayed before the next one
+        let energy = |range: std::ops::Range<usize>| output[range].iter().map(|x| x * x).sum::<f32>();
+        for beat in 0..4 {
+            let start = beat * 500;
+            assert!(energy(start..start + 50) > 100.0 * energy(start + 400..start + 500));
+        }
+    }
+    
+    #[test]
+    fn test_synthetic_source_frames() {
+        let format = AudioFormat { sample_rate: 44100, channels: 2 };
+        let mut source = SyntheticSource::new(TestSignal::Silence, 0.5, format, 256).unpaced();
+        
+        let (sender, receiver) = ring_channel(4);
+        source.start(sender).unwrap();
+        
+        let first = receiver.recv().unwrap();
+        let second = receiver.recv().unwrap();
+        assert_eq!(first.samples.len(), 512);
+        assert_eq!(first.channels, 2);
+        assert!(first.samples.iter().all(|&sample| sample == 0.0));
+        
+        // Frame timestamps advance with the signal, not the wall clock
+        let gap = second.timestamp.duration_since(first.timestamp).as_secs_f64();
+        assert!((gap - 256.0 / 44100.0).abs() < 1e-6);
+        
+        drop(receiver);
+        source.stop().unwrap();
+    }
+}
@@ -0,0 +1,373 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::Sender;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use super::analysis::{AudioFeatures, FrequencyData, StereoFeatures};
+use super::ring::RingSender;
+use super::{AudioData, AudioEvent, AudioQueueStats, ChannelData};
+
+/// Identifies trace files, in the header line
+const TRACE_FORMAT: &str = "wcr-viz-trace";
+
+/// Version of the trace format, bumped when `TraceFrame` changes incompatibly
+const TRACE_VERSION: u32 = 2;
+
+/// First line of a trace file
+#[derive(Serialize, Deserialize)]
+struct TraceHeader {
+    format: String,
+    version: u32,
+    /// Frequency of each spectrum bin (Hz), shared by every entry
+    #[serde(default)]
+    bin_frequencies: Vec<f32>,
+}
+
+/// One analyzed frame, on its own line after the header
+#[derive(Serialize, Deserialize)]
+struct TraceEntry {
+    /// Capture time relative to the first entry (seconds)
+    t: f64,
+    data: TraceFrame,
+}
+
+/// The traced part of an `AudioData`
+///
+/// Waveforms are left out, and spectrum bins are empty unless the trace was
+/// started with spectra.
+#[derive(Serialize, Deserialize)]
+struct TraceFrame {
+    spectrum: FrequencyData,
+    features: AudioFeatures,
+    bands: Vec<f32>,
+    left: FrequencyData,
+    right: FrequencyData,
+    stereo: StereoFeatures,
+}
+
+impl TraceFrame {
+    fn new(data: &AudioData, spectra: bool) -> Self {
+        let spectrum = |spectrum: &FrequencyData| FrequencyData {
+            bins: if spectra { spectrum.bins.clone() } else { Vec::new() },
+            bin_frequencies: Arc::default(),
+            ..*spectrum
+        };
+        
+        Self {
+            spectrum: spectrum(&data.spectrum),
+            features: data.features.clone(),
+            bands: data.bands.clone(),
+            left: spectrum(&data.left.spectrum),
+            right: spectrum(&data.right.spectrum),
+            stereo: data.stereo.clone(),
+        }
+    }
+    
+    /// Rebuild the `AudioData`, with empty waveforms
+    fn into_audio_data(self, bin_frequencies: &Arc<[f32]>, timestamp: Instant) -> AudioData {
+        let spectrum = |spectrum: FrequencyData| FrequencyData {
+            bin_frequencies: Arc::clone(bin_frequencies),
+            ..spectrum
+        };
+        let channel = |data: FrequencyData| ChannelData { waveform: Vec::new(), spectrum: spectrum(data) };
+        
+        AudioData {
+            waveform: Vec::new(),
+            spectrum: spectrum(self.spectrum),
+            features: self.features,
+            bands: self.bands,
+            left: channel(self.left),
+            right: channel(self.right),
+            stereo: self.stereo,
+            timestamp,
+            queue_stats: AudioQueueStats::default(),
+        }
+    }
+}
+
+/// A line for the trace writer thread
+#[derive(Serialize)]
+#[serde(untagged)]
+enum TraceLine {
+    Header(TraceHeader),
+    Entry(Box<TraceEntry>),
+}
+
+/// Writes the analyzed audio data stream to a trace file
+///
+/// Traces are JSON lines: a header with the spectrum's bin frequencies, then
+/// the features, bands and stereo image of each `AudioData` with its capture
+/// time, plus its spectra if asked for. Replaying one with `TraceReplay`
+/// reproduces what the renderer saw, except the waveforms, without the
+/// original audio or device. Lines are written on a thread of their own.
+/// Cloning gives another handle to the same recorder, like `AudioRecorder`.
+#[derive(Clone, Default)]
+pub struct TraceRecorder {
+    trace: Arc<Mutex<Option<TraceWriter>>>,
+}
+
+struct TraceWriter {
+    path: PathBuf,
+    spectra: bool,
+    lines: Sender<TraceLine>,
+    thread: JoinHandle<Result<u64>>,
+    start: Option<Instant>,
+}
+
+impl TraceRecorder {
+    /// Create a recorder that is not recording yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Start recording to `path`, with the spectra if `spectra` is set, finishing any trace in progress first
+    ///
+    /// Spectra make a trace about ten times larger.
+    pub fn start(&self, path: impl Into<PathBuf>, spectra: bool) -> Result<()> {
+        let path = path.into();
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create trace: {}", path.display()))?;
+        
+        let (lines, receiver) = crossbeam_channel::unbounded();
+        let thread = std::thread::Builder::new()
+            .name("trace-writer".to_string())
+            .spawn(move || {
+                let mut writer = BufWriter::new(file);
+                let mut entries = 0;
+                for line in receiver {
+                    serde_json::to_writer(&mut writer, &line)?;
+                    writer.write_all(b"\n")?;
+                    if matches!(line, TraceLine::Entry(_)) {
+                        entries += 1;
+                    }
+                }
+                writer.flush()?;
+                Ok(entries)
+            })
+            .context("Failed to start the trace writer")?;
+        
+        let trace = TraceWriter { path: path.clone(), spectra, lines, thread, start: None };
+        if let Some(previous) = self.trace.lock().replace(trace) {
+            Self::finish(previous);
+        }
+        
+        log::info!("Recording audio trace to {}", path.display());
+        Ok(())
+    }
+    
+    /// Stop recording, returning the path of the finished trace
+    pub fn stop(&self) -> Option<PathBuf> {
+        let trace = self.trace.lock().take()?;
+        Some(Self::finish(trace))
+    }
+    
+    /// Append analyzed data to the trace in progress, if any
+    ///
+    /// The header goes out with the first entry, once the bin frequencies are
+    /// known. A failing trace is finished and dropped, analysis carries on.
+    pub fn write(&self, data: &AudioData) {
+        let mut trace = self.trace.lock();
+        let Some(active) = trace.as_mut() else {
+            return;
+        };
+        
+        if active.start.is_none() {
+            Self::send_header(active, data.spectrum.bin_frequencies.to_vec());
+        }
+        let start = *active.start.get_or_insert(data.timestamp);
+        let entry = TraceEntry {
+            t: data.timestamp.saturating_duration_since(start).as_secs_f64(),
+            data: TraceFrame::new(data, active.spectra),
+        };
+        
+        // Only fails once the writer thread gave up
+        if active.lines.send(TraceLine::Entry(Box::new(entry))).is_err() {
+            if let Some(failed) = trace.take() {
+                Self::finish(failed);
+            }
+        }
+    }
+    
+    fn send_header(trace: &TraceWriter, bin_frequencies: Vec<f32>) {
+        let header = TraceHeader { format: TRACE_FORMAT.to_string(), version: TRACE_VERSION, bin_frequencies };
+        let _ = trace.lines.send(TraceLine::Header(header));
+    }
+    
+    fn finish(trace: TraceWriter) -> PathBuf {
+        // A trace without entries still gets its header
+        if trace.start.is_none() {
+            Self::send_header(&trace, Vec::new());
+        }
+        
+        let TraceWriter { path, lines, thread, .. } = trace;
+        drop(lines);
+        match thread.join() {
+            Ok(Ok(entries)) => log::info!("Recorded {} frames of audio trace to {}", entries, path.display()),
+            Ok(Err(e)) => log::error!("Audio trace recording failed: {:#}", e),
+            Err(_) => log::error!("Audio trace writer panicked"),
+        }
+        path
+    }
+}
+
+/// Plays a recorded trace back as `AudioEvent::DataReady` events
+///
+/// Entries are sent at their recorded pace, timestamped as if captured now,
+/// and never dropped: replay waits for the renderer instead.
+pub struct TraceReplay {
+    path: PathBuf,
+    bin_frequencies: Arc<[f32]>,
+    lines: std::io::Lines<BufReader<File>>,
+}
+
+impl TraceReplay {
+    /// Open a trace file and check its header
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open trace: {}", path.display()))?;
+        let mut lines = BufReader::new(file).lines();
+        
+        let header = lines.next()
+            .ok_or_else(|| anyhow::anyhow!("Trace is empty: {}", path.display()))??;
+        let header: TraceHeader = serde_json::from_str(&header)
+            .ok()
+            .filter(|header: &TraceHeader| header.format == TRACE_FORMAT)
+            .ok_or_else(|| anyhow::anyhow!("Not an audio trace: {}", path.display()))?;
+        if header.version != TRACE_VERSION {
+            anyhow::bail!(
+                "Trace {} has format version {}, this build reads version {}",
+                path.display(),
+                header.version,
+                TRACE_VERSION
+            );
+        }
+        
+        Ok(Self { path: path.to_path_buf(), bin_frequencies: header.bin_frequencies.into(), lines })
+    }
+    
+    /// Send every entry to `sender` at the recorded pace, until the trace ends or `is_running` is cleared
+    ///
+    /// Returns the number of entries sent.
+    pub fn run(self, sender: &RingSender<AudioEvent>, is_running: &RwLock<bool>) -> Result<u64> {
+        log::info!("Replaying audio trace {}", self.path.display());
+        let start = Instant::now();
+        let mut sent = 0;
+        
+        for (index, line) in self.lines.enumerate() {
+            if !*is_running.read() {
+                break;
+            }
+            
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: TraceEntry = serde_json::from_str(&line)
+                .with_context(|| format!("Invalid trace entry on line {}", index + 2))?;
+            
+            let due = start + Duration::from_secs_f64(entry.t.max(0.0));
+            let now = Instant::now();
+            if due > now {
+                std::thread::sleep(due - now);
+            }
+            
+            let data = entry.data.into_audio_data(&self.bin_frequencies, due);
+            if sender.send_blocking(AudioEvent::DataReady(Box::new(data))).is_err() {
+                break;
+            }
+            sent += 1;
+        }
+        
+        log::info!("Replayed {} frames of audio trace", sent);
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::ring::ring_channel;
+    use crate::audio::{AudioAnalyzer, AudioFrame};
+    use crate::config::AudioConfig;
+    
+    #[test]
+    fn test_trace_round_trip() {
+        let path = std::env::temp_dir().join("wcr_viz_trace_test.jsonl");
+        let config = AudioConfig::default();
+        let mut analyzer = AudioAnalyzer::new(&config).unwrap();
+        let start = Instant::now();
+        
+        // Three frames 10 ms apart, a hop each
+        let recorded: Vec<AudioData> = (0..3)
+            .flat_map(|index| {
+                let frame = AudioFrame {
+                    samples: (0..config.hop_size * 2).map(|n| ((n + index * 7) as f32 * 0.01).sin() * 0.5).collect(),
+                    timestamp: start + Duration::from_millis(10 * index as u64),
+                    sample_rate: config.sample_rate,
+                    channels: 2,
+                };
+                analyzer.process_frame(&frame).unwrap()
+            })
+            .collect();
+        
+        let recorder = TraceRecorder::new();
+        let mut sizes = Vec::new();
+        for spectra in [false, true] {
+            recorder.start(&path, spectra).unwrap();
+            for data in &recorded {
+                recorder.write(data);
+            }
+            assert_eq!(recorder.stop(), Some(path.clone()));
+            sizes.push(std::fs::metadata(&path).unwrap().len());
+            
+            let (sender, receiver) = ring_channel(1);
+            let replay = TraceReplay::open(&path).unwrap();
+            let is_running = RwLock::new(true);
+            let replay_thread = std::thread::spawn(move || replay.run(&sender, &is_running).unwrap());
+            
+            // A queue of one still gets every entry, replay waits for the receiver
+            let replayed: Vec<AudioData> = (0..3)
+                .map(|_| match receiver.recv_timeout(Duration::from_secs(2)).unwrap() {
+                    AudioEvent::DataReady(data) => *data,
+                    other => panic!("unexpected event {:?}", other),
+                })
+                .collect();
+            assert_eq!(replay_thread.join().unwrap(), 3);
+            let _ = std::fs::remove_file(&path);
+            
+            for (original, replayed) in recorded.iter().zip(&replayed) {
+                assert!(replayed.waveform.is_empty() && replayed.right.waveform.is_empty());
+                assert_eq!(original.spectrum.bin_frequencies, replayed.spectrum.bin_frequencies);
+                assert_eq!(original.right.spectrum.bin_frequencies, replayed.right.spectrum.bin_frequencies);
+                if spectra {
+                    assert_eq!(original.spectrum.bins, replayed.spectrum.bins);
+                    assert_eq!(original.left.spectrum.bins, replayed.left.spectrum.bins);
+                } else {
+                    assert!(replayed.spectrum.bins.is_empty());
+                }
+                assert_eq!(original.spectrum.peak_frequency, replayed.spectrum.peak_frequency);
+                assert_eq!(original.bands, replayed.bands);
+                assert_eq!(original.features.bass, replayed.features.bass);
+                assert_eq!(original.stereo.correlation, replayed.stereo.correlation);
+            }
+            let spacing = replayed[2].timestamp.duration_since(replayed[0].timestamp);
+            assert!((spacing.as_secs_f64() - 0.02).abs() < 1e-6);
+        }
+        
+        // The spectra dominate a trace, the bin frequencies are only in the header
+        assert!(sizes[0] * 4 < sizes[1], "trace sizes {:?}", sizes);
+        
+        // A trace without entries can still be opened
+        recorder.start(&path, false).unwrap();
+        recorder.stop();
+        let replay = TraceReplay::open(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(replay.bin_frequencies.is_empty());
+    }
+}
@@ -0,0 +1,109 @@
+use std::f64::consts::PI;
+
+use crate::config::WindowFunction;
+
+/// Cosine-sum coefficients, a0 - a1 cos + a2 cos - ...
+const HANN: &[f64] = &[0.5, 0.5];
+const HAMMING: &[f64] = &[0.54, 0.46];
+const BLACKMAN_HARRIS: &[f64] = &[0.35875, 0.48829, 0.14128, 0.01168];
+const FLAT_TOP: &[f64] = &[0.21557895, 0.41663158, 0.277263158, 0.083578947, 0.006947368];
+
+impl WindowFunction {
+    /// Window coefficients over `size` samples, scaled to the coherent gain of a Hann window
+    ///
+    /// A tone then reads the same magnitude whichever window is chosen: the
+    /// window changes peak widths and leakage, not the levels features see.
+    pub fn coefficients(self, size: usize) -> Vec<f32> {
+        let window = match self {
+            WindowFunction::Hann => cosine_sum(HANN, size),
+            WindowFunction::Hamming => cosine_sum(HAMMING, size),
+            WindowFunction::BlackmanHarris => cosine_sum(BLACKMAN_HARRIS, size),
+            WindowFunction::FlatTop => cosine_sum(FLAT_TOP, size),
+            WindowFunction::Kaiser { beta } => kaiser(beta as f64, size),
+        };
+        
+        let sum: f64 = window.iter().sum();
+        let scale = if sum > 0.0 { cosine_sum(HANN, size).iter().sum::<f64>() / sum } else { 1.0 };
+        window.iter().map(|&value| (value * scale) as f32).collect()
+    }
+}
+
+/// Position of each sample across a symmetric window, 0.0 - 1.0
+fn positions(size: usize) -> impl Iterator<Item = f64> {
+    let last = size.saturating_sub(1).max(1) as f64;
+    (0..size).map(move |i| i as f64 / last)
+}
+
+fn cosine_sum(coefficients: &[f64], size: usize) -> Vec<f64> {
+    positions(size)
+        .map(|position| {
+            coefficients
+                .iter()
+                .enumerate()
+                .map(|(k, a)| {
+                    let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                    sign * a * (2.0 * PI * k as f64 * position).cos()
+                })
+                .sum()
+        })
+        .collect()
+}
+
+fn kaiser(beta: f64, size: usize) -> Vec<f64> {
+    let peak = bessel_i0(beta);
+    positions(size)
+        .map(|position| {
+            let x = 2.0 * position - 1.0;
+            bessel_i0(beta * (1.0 - x * x).max(0.0).sqrt()) / peak
+        })
+        .collect()
+}
+
+/// Zeroth-order modified Bessel function of the first kind, from its power series
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    for k in 1..100 {
+        term *= (x / (2.0 * k as f64)).powi(2);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const WINDOWS: [WindowFunction; 5] = [
+        WindowFunction::Hann,
+        WindowFunction::Hamming,
+        WindowFunction::BlackmanHarris,
+        WindowFunction::FlatTop,
+        WindowFunction::Kaiser { beta: 8.6 },
+    ];
+    
+    #[test]
+    fn test_windows_are_symmetric_with_hann_gain() {
+        let hann_sum: f32 = WindowFunction::Hann.coefficients(1024).iter().sum();
+        for window in WINDOWS {
+            let coefficients = window.coefficients(1024);
+            assert_eq!(coefficients.len(), 1024);
+            for (a, b) in coefficients.iter().zip(coefficients.iter().rev()) {
+                assert!((a - b).abs() < 1e-5, "{:?} is not symmetric", window);
+            }
+            
+            // Peak in the middle, and the same sum as Hann so tones read the same
+            let peak = coefficients.iter().copied().fold(0.0f32, f32::max);
+            assert!((coefficients[511] - peak).abs() < 1e-3, "{:?} peaks off centre", window);
+            let sum: f32 = coefficients.iter().sum();
+            assert!((sum - hann_sum).abs() < hann_sum * 1e-4, "{:?} sums to {}, Hann to {}", window, sum, hann_sum);
+        }
+        
+        // Kaiser at beta 0 is rectangular
+        let rectangle = WindowFunction::Kaiser { beta: 0.0 }.coefficients(16);
+        assert!(rectangle.iter().all(|&value| (value - rectangle[0]).abs() < 1e-6));
+    }
+}
@@ -1,7 +1,10 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -15,6 +18,10 @@ pub struct AudioConfig {
     /// Audio device name (None for default)
     pub device_name: Option<String>,
     
+    /// Devices to fall back to, most preferred first, when the current one disappears
+    #[serde(default)]
+    pub device_priority: Vec<String>,
+    
     /// Sample rate (Hz)
     pub sample_rate: u32,
     
@@ -24,14 +31,133 @@ pub struct AudioConfig {
     /// FFT size for frequency analysis
     pub fft_size: usize,
     
+    /// Samples in each analysis window, zero-padded to `fft_size`
+    #[serde(default = "default_window_size")]
+    pub window_size: usize,
+    
+    /// Samples between analysis windows, so features arrive at `sample_rate / hop_size` Hz
+    /// whatever buffer size the device delivers (windows overlap when it is below `window_size`)
+    #[serde(default = "default_hop_size")]
+    pub hop_size: usize,
+    
+    /// Window function applied before each FFT
+    #[serde(default)]
+    pub window_function: WindowFunction,
+    
+    /// Analyses at other FFT sizes, each replacing the spectrum over its frequency range
+    ///
+    /// A long FFT for the bass and a short one for the highs give precise low
+    /// bands without slowing down transients up top.
+    #[serde(default)]
+    pub resolutions: Vec<SpectrumResolution>,
+    
     /// Audio capture mode
     pub capture_mode: AudioCaptureMode,
     
     /// Enable system audio loopback (Windows WASAPI)
     pub enable_loopback: bool,
     
-    /// Audio latency target (milliseconds)
+    /// Delay between capturing audio and showing it (milliseconds)
+    ///
+    /// Raise it when the visuals run ahead of the music, lower it when they trail.
+    /// Can be nudged at runtime with the `[` and `]` keys.
     pub target_latency_ms: f32,
+    
+    /// Audio file to play back when using `AudioCaptureMode::File`
+    #[serde(default)]
+    pub input_file: Option<PathBuf>,
+    
+    /// Also play the input file through the default output device
+    #[serde(default = "default_true")]
+    pub file_playback_output: bool,
+    
+    /// Test signal generated when using `AudioCaptureMode::Synthetic`
+    #[serde(default)]
+    pub test_signal: TestSignal,
+    
+    /// Peak amplitude of the generated test signal (0.0 - 1.0)
+    #[serde(default = "default_test_signal_amplitude")]
+    pub test_signal_amplitude: f32,
+    
+    /// Raw PCM format read when using `AudioCaptureMode::Pcm`, or received as raw UDP packets
+    #[serde(default)]
+    pub pcm_format: PcmFormat,
+    
+    /// Named pipe or file to read raw PCM from (None reads stdin)
+    #[serde(default)]
+    pub pcm_path: Option<PathBuf>,
+    
+    /// Address to receive packets on when using `AudioCaptureMode::Network`
+    #[serde(default = "default_network_address")]
+    pub network_address: SocketAddr,
+    
+    /// Packet format received when using `AudioCaptureMode::Network`
+    #[serde(default)]
+    pub network_payload: NetworkPayload,
+    
+    /// Network audio buffered against packet jitter before playback starts (milliseconds)
+    #[serde(default = "default_network_jitter_ms")]
+    pub network_jitter_ms: f32,
+    
+    /// Gain applied to the input device when using `AudioCaptureMode::Both`
+    #[serde(default = "default_gain")]
+    pub input_gain: f32,
+    
+    /// Gain applied to the loopback device when using `AudioCaptureMode::Both`
+    #[serde(default = "default_gain")]
+    pub loopback_gain: f32,
+    
+    /// Captured frames buffered for analysis before the oldest are dropped
+    #[serde(default = "default_frame_queue_size")]
+    pub frame_queue_size: usize,
+    
+    /// Audio events buffered for the renderer before the oldest are dropped
+    #[serde(default = "default_event_queue_size")]
+    pub event_queue_size: usize,
+    
+    /// RMS level below which the audio counts as silent (dBFS)
+    #[serde(default = "default_silence_threshold_db")]
+    pub silence_threshold_db: f32,
+    
+    /// How long the level must stay below the silence threshold before silence is reported (milliseconds)
+    #[serde(default = "default_silence_hold_ms")]
+    pub silence_hold_ms: f32,
+    
+    /// Normalize the input level before analysis, so features don't depend on the capture gain
+    #[serde(default)]
+    pub agc_enabled: bool,
+    
+    /// RMS level the automatic gain control aims for (dBFS)
+    #[serde(default = "default_agc_target_db")]
+    pub agc_target_db: f32,
+    
+    /// How fast the gain comes down when the input gets louder (milliseconds)
+    #[serde(default = "default_agc_attack_ms")]
+    pub agc_attack_ms: f32,
+    
+    /// How fast the gain goes up when the input gets quieter (milliseconds)
+    #[serde(default = "default_agc_release_ms")]
+    pub agc_release_ms: f32,
+    
+    /// Largest gain the automatic gain control applies (dB)
+    #[serde(default = "default_agc_max_gain_db")]
+    pub agc_max_gain_db: f32,
+    
+    /// Frequency spacing of the filterbank bands in `AudioData::bands`
+    #[serde(default)]
+    pub band_scale: BandScale,
+    
+    /// Number of filterbank bands
+    #[serde(default = "default_band_count")]
+    pub band_count: usize,
+    
+    /// Lowest frequency covered by the filterbank (Hz)
+    #[serde(default = "default_band_min_hz")]
+    pub band_min_hz: f32,
+    
+    /// Highest frequency covered by the filterbank, capped at the Nyquist frequency (Hz)
+    #[serde(default = "default_band_max_hz")]
+    pub band_max_hz: f32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,6 +168,232 @@ pub enum AudioCaptureMode {
     Loopback,
     /// Capture both input and system audio
     Both,
+    /// Play back an audio file in real time
+    File,
+    /// Generate a built-in test signal
+    Synthetic,
+    /// Read raw interleaved PCM from stdin or a named pipe
+    Pcm,
+    /// Receive PCM packets over UDP
+    Network,
+}
+
+/// Window functions for spectrum analysis
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum WindowFunction {
+    /// Good all-round choice
+    #[default]
+    Hann,
+    /// Lower first sidelobe than Hann, slower falloff
+    Hamming,
+    /// Very low sidelobes (4-term), wider peaks
+    BlackmanHarris,
+    /// Accurate peak amplitudes, very wide peaks
+    FlatTop,
+    /// Adjustable trade-off: higher `beta` lowers the sidelobes and widens the peaks
+    Kaiser { beta: f32 },
+}
+
+/// An extra analysis at another FFT size, covering part of the spectrum
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpectrumResolution {
+    /// FFT size of this analysis
+    pub fft_size: usize,
+    
+    /// Samples in each analysis window, zero-padded to `fft_size`
+    pub window_size: usize,
+    
+    /// Lowest frequency taken from this analysis (Hz)
+    #[serde(default)]
+    pub min_hz: f32,
+    
+    /// Highest frequency taken from this analysis (Hz)
+    #[serde(default = "default_resolution_max_hz")]
+    pub max_hz: f32,
+}
+
+/// How filterbank bands are spaced over the spectrum
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BandScale {
+    /// Equal width in Hz
+    Linear,
+    /// Equal width in octaves
+    Log,
+    /// Equal width in mels, close to how pitch is heard
+    #[default]
+    Mel,
+    /// Equal width in critical bands (Bark)
+    Bark,
+}
+
+/// Packet format of network audio input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NetworkPayload {
+    /// RTP packets carrying L16 audio (RFC 3551)
+    #[default]
+    Rtp,
+    /// Bare datagrams of interleaved PCM in `pcm_format`
+    Raw,
+}
+
+/// Sample encoding of raw PCM input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PcmEncoding {
+    F32Le,
+    F32Be,
+    S16Le,
+    S16Be,
+    S32Le,
+    S32Be,
+    U8,
+}
+
+impl PcmEncoding {
+    /// Size of a single sample in bytes
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            PcmEncoding::F32Le | PcmEncoding::F32Be => 4,
+            PcmEncoding::S16Le | PcmEncoding::S16Be => 2,
+            PcmEncoding::S32Le | PcmEncoding::S32Be => 4,
+            PcmEncoding::U8 => 1,
+        }
+    }
+}
+
+/// Raw PCM stream layout, written as `<encoding>:<rate>:<channels>` (e.g. `f32le:48000:2`)
+/// on the command line and in the config file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcmFormat {
+    pub encoding: PcmEncoding,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl Default for PcmFormat {
+    fn default() -> Self {
+        Self {
+            encoding: PcmEncoding::F32Le,
+            sample_rate: 48000,
+            channels: 2,
+        }
+    }
+}
+
+impl FromStr for PcmFormat {
+    type Err = anyhow::Error;
+    
+    /// Parse a format spec using ffmpeg's sample format names, e.g. `s16le:44100:2`
+    fn from_str(spec: &str) -> Result<Self> {
+        let parts: Vec<&str> = spec.split(':').collect();
+        if parts.len() != 3 {
+            anyhow::bail!("Invalid PCM format '{}', expected <encoding>:<rate>:<channels>", spec);
+        }
+        
+        let encoding = match parts[0].to_lowercase().as_str() {
+            "f32le" => PcmEncoding::F32Le,
+            "f32be" => PcmEncoding::F32Be,
+            "s16le" => PcmEncoding::S16Le,
+            "s16be" => PcmEncoding::S16Be,
+            "s32le" => PcmEncoding::S32Le,
+            "s32be" => PcmEncoding::S32Be,
+            "u8" => PcmEncoding::U8,
+            other => anyhow::bail!("Unknown PCM encoding '{}' (expected f32le, f32be, s16le, s16be, s32le, s32be or u8)", other),
+        };
+        
+        let sample_rate = parts[1].parse::<u32>()
+            .with_context(|| format!("Invalid sample rate '{}' in PCM format '{}'", parts[1], spec))?;
+        let channels = parts[2].parse::<u16>()
+            .with_context(|| format!("Invalid channel count '{}' in PCM format '{}'", parts[2], spec))?;
+        
+        if sample_rate == 0 || channels == 0 {
+            anyhow::bail!("PCM sample rate and channel count must be greater than 0");
+        }
+        
+        Ok(Self { encoding, sample_rate, channels })
+    }
+}
+
+impl fmt::Display for PcmFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let encoding = match self.encoding {
+            PcmEncoding::F32Le => "f32le",
+            PcmEncoding::F32Be => "f32be",
+            PcmEncoding::S16Le => "s16le",
+            PcmEncoding::S16Be => "s16be",
+            PcmEncoding::S32Le => "s32le",
+            PcmEncoding::S32Be => "s32be",
+            PcmEncoding::U8 => "u8",
+        };
+        write!(f, "{}:{}:{}", encoding, self.sample_rate, self.channels)
+    }
+}
+
+impl Serialize for PcmFormat {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PcmFormat {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let spec = String::deserialize(deserializer)?;
+        spec.parse().map_err(|e: anyhow::Error| serde::de::Error::custom(format!("{:#}", e)))
+    }
+}
+
+/// Built-in test signals for the synthetic audio source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TestSignal {
+    /// Pure sine tone
+    Sine { frequency: f32 },
+    /// Logarithmic sine sweep, restarting every `duration` seconds
+    Sweep { start_frequency: f32, end_frequency: f32, duration: f32 },
+    /// Uniform white noise
+    WhiteNoise,
+    /// Pink (1/f) noise
+    PinkNoise,
+    /// Kick drum click track
+    Kick { bpm: f32 },
+    /// Digital silence
+    Silence,
+}
+
+impl Default for TestSignal {
+    fn default() -> Self {
+        TestSignal::Sine { frequency: 440.0 }
+    }
+}
+
+impl FromStr for TestSignal {
+    type Err = anyhow::Error;
+    
+    /// Parse a signal spec such as `sine:440`, `sweep:20:20000:10`, `white`, `pink`, `kick:128` or `silence`
+    fn from_str(spec: &str) -> Result<Self> {
+        let parts: Vec<&str> = spec.split(':').collect();
+        let param = |index: usize, default: f32| -> Result<f32> {
+            match parts.get(index) {
+                Some(value) => value.parse::<f32>()
+                    .with_context(|| format!("Invalid number '{}' in signal '{}'", value, spec)),
+                None => Ok(default),
+            }
+        };
+        
+        let signal = match parts[0].to_lowercase().as_str() {
+            "sine" => TestSignal::Sine { frequency: param(1, 440.0)? },
+            "sweep" => TestSignal::Sweep {
+                start_frequency: param(1, 20.0)?,
+                end_frequency: param(2, 20000.0)?,
+                duration: param(3, 10.0)?,
+            },
+            "white" | "noise" => TestSignal::WhiteNoise,
+            "pink" => TestSignal::PinkNoise,
+            "kick" => TestSignal::Kick { bpm: param(1, 120.0)? },
+            "silence" => TestSignal::Silence,
+            other => anyhow::bail!("Unknown test signal '{}' (expected sine, sweep, white, pink, kick or silence)", other),
+        };
+        
+        Ok(signal)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,6 +418,32 @@ pub struct GraphicsConfig {
     
     /// Texture filtering mode
     pub texture_filtering: TextureFiltering,
+    
+    /// What the renderer does while the audio is silent
+    #[serde(default)]
+    pub idle_behavior: IdleBehavior,
+    
+    /// Frame rate while idling with `IdleBehavior::LowFrameRate`
+    #[serde(default = "default_idle_fps")]
+    pub idle_fps: u32,
+    
+    /// Name of the preset shown while idling with `IdleBehavior::Preset`
+    #[serde(default)]
+    pub idle_preset: Option<String>,
+}
+
+/// Renderer behaviour between songs, while no audio is coming in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IdleBehavior {
+    /// Keep rendering as usual
+    #[default]
+    Continue,
+    
+    /// Drop to `idle_fps` to save GPU time
+    LowFrameRate,
+    
+    /// Switch to `idle_preset` until audio returns
+    Preset,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -93,18 +471,133 @@ pub struct UiConfig {
     pub auto_hide_ui_seconds: f32,
 }
 
+fn default_true() -> bool {
+    true
+}
+
+fn default_test_signal_amplitude() -> f32 {
+    0.5
+}
+
+fn default_gain() -> f32 {
+    1.0
+}
+
+fn default_network_address() -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], 5004))
+}
+
+fn default_window_size() -> usize {
+    1024
+}
+
+fn default_hop_size() -> usize {
+    256
+}
+
+fn default_network_jitter_ms() -> f32 {
+    40.0
+}
+
+fn default_frame_queue_size() -> usize {
+    32
+}
+
+fn default_event_queue_size() -> usize {
+    32
+}
+
+fn default_silence_threshold_db() -> f32 {
+    -60.0
+}
+
+fn default_silence_hold_ms() -> f32 {
+    2000.0
+}
+
+fn default_agc_target_db() -> f32 {
+    -20.0
+}
+
+fn default_agc_attack_ms() -> f32 {
+    100.0
+}
+
+fn default_agc_release_ms() -> f32 {
+    3000.0
+}
+
+fn default_agc_max_gain_db() -> f32 {
+    30.0
+}
+
+fn default_resolution_max_hz() -> f32 {
+    f32::INFINITY
+}
+
+fn default_band_count() -> usize {
+    32
+}
+
+fn default_band_min_hz() -> f32 {
+    20.0
+}
+
+fn default_band_max_hz() -> f32 {
+    20000.0
+}
+
+fn default_idle_fps() -> u32 {
+    5
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            device_name: None,
+            device_priority: Vec::new(),
+            sample_rate: 44100,
+            buffer_size: 1024,
+            fft_size: 2048,
+            window_size: default_window_size(),
+            hop_size: default_hop_size(),
+            window_function: WindowFunction::default(),
+            resolutions: Vec::new(),
+            capture_mode: AudioCaptureMode::Loopback,
+            enable_loopback: true,
+            target_latency_ms: 50.0,
+            input_file: None,
+            file_playback_output: true,
+            test_signal: TestSignal::default(),
+            test_signal_amplitude: default_test_signal_amplitude(),
+            pcm_format: PcmFormat::default(),
+            pcm_path: None,
+            network_address: default_network_address(),
+            network_payload: NetworkPayload::default(),
+            network_jitter_ms: default_network_jitter_ms(),
+            input_gain: default_gain(),
+            loopback_gain: default_gain(),
+            frame_queue_size: default_frame_queue_size(),
+            event_queue_size: default_event_queue_size(),
+            silence_threshold_db: default_silence_threshold_db(),
+            silence_hold_ms: default_silence_hold_ms(),
+            agc_enabled: false,
+            agc_target_db: default_agc_target_db(),
+            agc_attack_ms: default_agc_attack_ms(),
+            agc_release_ms: default_agc_release_ms(),
+            agc_max_gain_db: default_agc_max_gain_db(),
+            band_scale: BandScale::default(),
+            band_count: default_band_count(),
+            band_min_hz: default_band_min_hz(),
+            band_max_hz: default_band_max_hz(),
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
-            audio: AudioConfig {
-                device_name: None,
-                sample_rate: 44100,
-                buffer_size: 1024,
-                fft_size: 2048,
-                capture_mode: AudioCaptureMode::Loopback,
-                enable_loopback: true,
-                target_latency_ms: 50.0,
-            },
+            audio: AudioConfig::default(),
             graphics: GraphicsConfig {
                 target_fps: 60,
                 vsync: true,
@@ -113,6 +606,9 @@ impl Default for Config {
                 start_fullscreen: false,
                 multi_monitor: false,
                 texture_filtering: TextureFiltering::Linear,
+                idle_behavior: IdleBehavior::default(),
+                idle_fps: default_idle_fps(),
+                idle_preset: None,
             },
             ui: UiConfig {
                 show_fps: true,
@@ -176,11 +672,77 @@ impl Config {
             anyhow::bail!("FFT size must be a power of 2 and greater than 0");
         }
         
+        if self.audio.window_size == 0 || self.audio.window_size > self.audio.fft_size {
+            anyhow::bail!("Analysis window size must be greater than 0 and at most the FFT size");
+        }
+        
+        if self.audio.hop_size == 0 || self.audio.hop_size > self.audio.window_size {
+            anyhow::bail!("Hop size must be greater than 0 and at most the analysis window size");
+        }
+        
+        if let WindowFunction::Kaiser { beta } = self.audio.window_function {
+            if !beta.is_finite() || beta < 0.0 {
+                anyhow::bail!("Kaiser window beta must be finite and non-negative");
+            }
+        }
+        
+        for resolution in &self.audio.resolutions {
+            if resolution.fft_size == 0 || !resolution.fft_size.is_power_of_two() {
+                anyhow::bail!("Resolution FFT size must be a power of 2 and greater than 0");
+            }
+            if resolution.window_size == 0 || resolution.window_size > resolution.fft_size {
+                anyhow::bail!("Resolution window size must be greater than 0 and at most its FFT size");
+            }
+            if !(resolution.min_hz >= 0.0 && resolution.min_hz < resolution.max_hz) {
+                anyhow::bail!("Resolution frequency range must be non-negative, with the minimum below the maximum");
+            }
+        }
+        
+        if matches!(self.audio.capture_mode, AudioCaptureMode::File) && self.audio.input_file.is_none() {
+            anyhow::bail!("File capture mode requires an input file");
+        }
+        
+        if !(0.0..=1.0).contains(&self.audio.test_signal_amplitude) {
+            anyhow::bail!("Test signal amplitude must be between 0.0 and 1.0");
+        }
+        
+        if !(self.audio.input_gain >= 0.0 && self.audio.loopback_gain >= 0.0) {
+            anyhow::bail!("Input and loopback gains must be non-negative");
+        }
+        
+        if self.audio.network_jitter_ms < 0.0 {
+            anyhow::bail!("Network jitter buffer must be non-negative");
+        }
+        
+        if self.audio.frame_queue_size == 0 || self.audio.event_queue_size == 0 {
+            anyhow::bail!("Audio queue sizes must be greater than 0");
+        }
+        
+        if self.audio.silence_hold_ms < 0.0 {
+            anyhow::bail!("Silence hold time must be non-negative");
+        }
+        
+        if self.audio.agc_attack_ms < 0.0 || self.audio.agc_release_ms < 0.0 || self.audio.agc_max_gain_db < 0.0 {
+            anyhow::bail!("AGC attack, release and max gain must be non-negative");
+        }
+        
+        if self.audio.band_count == 0 {
+            anyhow::bail!("Band count must be greater than 0");
+        }
+        
+        if !(self.audio.band_min_hz > 0.0 && self.audio.band_min_hz < self.audio.band_max_hz) {
+            anyhow::bail!("Band frequency range must be positive, with the minimum below the maximum");
+        }
+        
         // Validate graphics settings
         if self.graphics.target_fps == 0 {
             anyhow::bail!("Target FPS must be greater than 0");
         }
         
+        if self.graphics.idle_fps == 0 {
+            anyhow::bail!("Idle FPS must be greater than 0");
+        }
+        
         if self.graphics.window_width == 0 || self.graphics.window_height == 0 {
             anyhow::bail!("Window dimensions must be greater than 0");
         }
@@ -192,4 +754,30 @@ impl Config {
         
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_validation() {
+        assert!(Config::default().validate().is_ok());
+        
+        let mut config = Config::default();
+        config.audio.band_min_hz = 0.0;
+        assert!(config.validate().is_err());
+    }
+    
+    #[test]
+    fn test_pcm_format_in_config_file() {
+        let config: Config = toml::from_str(&toml::to_string(&Config::default()).unwrap()).unwrap();
+        assert_eq!(config.audio.pcm_format, PcmFormat::default());
+        
+        // Written the same way as on the command line
+        let mut audio = toml::Table::try_from(AudioConfig::default()).unwrap();
+        assert_eq!(audio["pcm_format"].as_str(), Some("f32le:48000:2"));
+        audio.insert("pcm_format".to_string(), "s16be:44100:1".into());
+        let audio: AudioConfig = audio.try_into().unwrap();
+        assert_eq!(audio.pcm_format, PcmFormat { encoding: PcmEncoding::S16Be, sample_rate: 44100, channels: 1 });
+    }
+}
@@ -1,16 +1,18 @@
 use anyhow::Result;
+use log::{info, error, warn};
 use crossbeam_channel::Receiver;
-use log::{info, error};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use winit::{
     application::ApplicationHandler,
     event::{WindowEvent, ElementState},
-    event_loop::{ActiveEventLoop, EventLoop},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
     window::WindowId,
     keyboard::{KeyCode, PhysicalKey},
 };
 
-use crate::audio::{AudioEvent, AudioData};
+use crate::audio::{AudioEvent, AudioData, AudioRecorder, LatencyCompensator, RingReceiver};
+use crate::config::IdleBehavior;
 use crate::preset::PresetManager;
 use crate::ui::PresetUI;
 use crate::iced_integration::IcedIntegration;
@@ -22,6 +24,12 @@ pub mod window;
 use renderer::Renderer;
 use window::WindowManager;
 
+/// How far one press of the A/V offset keys moves the offset (milliseconds)
+const AV_OFFSET_STEP_MS: f32 = 10.0;
+
+/// How often audio events are checked between frames while rendering at the idle frame rate
+const IDLE_EVENT_INTERVAL: Duration = Duration::from_millis(20);
+
 /// Graphics system configuration
 #[derive(Debug, Clone)]
 pub struct GraphicsConfig {
@@ -42,6 +50,18 @@ pub struct GraphicsConfig {
     
     /// Enable fullscreen on startup
     pub fullscreen: bool,
+    
+    /// Delay between capturing audio and showing it (milliseconds)
+    pub av_offset_ms: f32,
+    
+    /// What to do while the audio is silent
+    pub idle_behavior: IdleBehavior,
+    
+    /// Frame rate while idling with `IdleBehavior::LowFrameRate`
+    pub idle_fps: u32,
+    
+    /// Preset shown while idling with `IdleBehavior::Preset`
+    pub idle_preset: Option<String>,
 }
 
 impl Default for GraphicsConfig {
@@ -53,6 +73,10 @@ impl Default for GraphicsConfig {
             target_fps: 60,
             vsync: true,
             fullscreen: false,
+            av_offset_ms: 50.0,
+            idle_behavior: IdleBehavior::default(),
+            idle_fps: 5,
+            idle_preset: None,
         }
     }
 }
@@ -62,8 +86,16 @@ struct AppState {
     window_manager: Option<WindowManager>,
     renderer: Option<Renderer>,
     iced_integration: Option<IcedIntegration>,
-    audio_receiver: Receiver<AudioEvent>,
+    audio_receiver: RingReceiver<AudioEvent>,
+    control_receiver: Receiver<AudioEvent>,
+    audio_recorder: AudioRecorder,
     current_audio_data: Option<AudioData>,
+    latency: LatencyCompensator,
+    /// Whether the audio is silent and the idle behaviour applies
+    idle: bool,
+    last_frame: Instant,
+    /// Preset to go back to when leaving the idle preset
+    resume_preset: Option<usize>,
     config: GraphicsConfig,
     is_running: bool,
     preset_manager: PresetManager,
@@ -141,6 +173,29 @@ impl ApplicationHandler for AppState {
                         }
                     }
                     
+                    // Nudge the A/V offset until beats line up
+                    let nudge = match key_code {
+                        KeyCode::BracketLeft => Some(-AV_OFFSET_STEP_MS),
+                        KeyCode::BracketRight => Some(AV_OFFSET_STEP_MS),
+                        _ => None,
+                    };
+                    if let Some(delta) = nudge {
+                        let offset = self.latency.nudge(delta);
+                        match self.latency.measured_latency_ms() {
+                            Some(measured) => info!("A/V offset {:.0} ms (audio arrives after {:.0} ms)", offset, measured),
+                            None => info!("A/V offset {:.0} ms", offset),
+                        }
+                    }
+                    
+                    // Start or stop recording the captured audio
+                    if key_code == KeyCode::KeyR {
+                        if self.audio_recorder.is_recording() {
+                            self.audio_recorder.stop();
+                        } else if let Err(e) = self.audio_recorder.start(recording_file_name()) {
+                            error!("Failed to start recording: {:#}", e);
+                        }
+                    }
+                    
                     // Handle preset navigation
                     if let Some(iced_integration) = &mut self.iced_integration {
                         if iced_integration.is_overlay_visible() {
@@ -171,22 +226,17 @@ impl ApplicationHandler for AppState {
                     // Handle scale factor change
                 }
                 WindowEvent::RedrawRequested => {
-                    // Check for audio data updates
-                    while let Ok(audio_event) = self.audio_receiver.try_recv() {
-                        match audio_event {
-                            AudioEvent::DataReady(data) => {
-                                self.current_audio_data = Some(data);
-                                if let (Some(ref audio_data), Some(ref mut renderer)) = 
-                                    (&self.current_audio_data, &mut self.renderer) {
-                                    if let Err(e) = renderer.update_audio_data(audio_data) {
-                                        error!("Failed to update audio data: {}", e);
-                                    }
-                                }
-                            }
-                            AudioEvent::Error(e) => {
-                                error!("Audio error: {}", e);
+                    // Audio events were handled in `about_to_wait`
+                    let now = Instant::now();
+                    self.last_frame = now;
+                    
+                    // Show the audio that lines up with what is being heard right now
+                    if let Some(data) = self.latency.poll(now) {
+                        let audio_data = self.current_audio_data.insert(data);
+                        if let Some(renderer) = &mut self.renderer {
+                            if let Err(e) = renderer.update_audio_data(audio_data) {
+                                error!("Failed to update audio data: {}", e);
                             }
-                            _ => {}
                         }
                     }
                     
@@ -227,23 +277,115 @@ impl ApplicationHandler for AppState {
                         }
                     }
                     
-                    // Request next frame
-                    window_manager.window.request_redraw();
+                    // Request next frame, unless idling at a low frame rate
+                    if !(self.idle && self.config.idle_behavior == IdleBehavior::LowFrameRate) {
+                        window_manager.window.request_redraw();
+                    }
                 }
                 _ => {}
             }
         }
     }
 
-    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
-        // Request redraw continuously for real-time visualization
-        if let Some(window_manager) = &self.window_manager {
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        self.handle_audio_events();
+        
+        let Some(window_manager) = &self.window_manager else {
+            return;
+        };
+        
+        if self.is_throttled() {
+            // Draw at the idle frame rate, but keep waking up for audio events
+            let now = Instant::now();
+            let next_frame = self.last_frame + Duration::from_secs_f32(1.0 / self.config.idle_fps.max(1) as f32);
+            if next_frame <= now {
+                window_manager.window.request_redraw();
+            }
+            event_loop.set_control_flow(ControlFlow::WaitUntil(next_frame.max(now).min(now + IDLE_EVENT_INTERVAL)));
+        } else {
+            // Request redraw continuously for real-time visualization
+            event_loop.set_control_flow(ControlFlow::Wait);
             window_manager.window.request_redraw();
         }
     }
 }
 
 impl AppState {
+    /// Handle queued audio events, queueing analyzed data for display
+    fn handle_audio_events(&mut self) {
+        let now = Instant::now();
+        // Control events first: each was sent before any data that follows it
+        while let Ok(audio_event) = self.control_receiver.try_recv() {
+            self.handle_audio_event(audio_event, now);
+        }
+        while let Ok(audio_event) = self.audio_receiver.try_recv() {
+            self.handle_audio_event(audio_event, now);
+        }
+    }
+    
+    fn handle_audio_event(&mut self, audio_event: AudioEvent, now: Instant) {
+        match audio_event {
+            AudioEvent::DataReady(data) => {
+                self.latency.push(*data, now);
+            }
+            AudioEvent::DeviceChanged(device) => {
+                info!("Audio device changed to: {}", device);
+            }
+            AudioEvent::Error(e) => {
+                error!("Audio error: {}", e);
+            }
+            AudioEvent::SilenceStarted => self.set_idle(true),
+            AudioEvent::SilenceEnded => self.set_idle(false),
+            _ => {}
+        }
+    }
+    
+    /// Whether frames are currently drawn at the idle frame rate
+    fn is_throttled(&self) -> bool {
+        self.idle && self.config.idle_behavior == IdleBehavior::LowFrameRate
+    }
+    
+    /// Enter or leave the configured idle behaviour
+    fn set_idle(&mut self, idle: bool) {
+        if self.idle == idle {
+            return;
+        }
+        self.idle = idle;
+        
+        match (idle, self.config.idle_behavior) {
+            (true, IdleBehavior::LowFrameRate) => info!("Audio is silent, rendering at {} FPS", self.config.idle_fps),
+            (true, _) => info!("Audio is silent"),
+            (false, _) => info!("Audio resumed"),
+        }
+        
+        if self.config.idle_behavior != IdleBehavior::Preset {
+            return;
+        }
+        
+        let index = if idle {
+            let Some(name) = &self.config.idle_preset else {
+                warn!("Idle behaviour is Preset, but no idle_preset is configured");
+                return;
+            };
+            let Some(index) = self.preset_manager.get_presets().iter().position(|preset| &preset.metadata.name == name) else {
+                warn!("Idle preset not found: {}", name);
+                return;
+            };
+            self.resume_preset = Some(self.preset_manager.current_preset_index);
+            index
+        } else {
+            let Some(index) = self.resume_preset.take() else {
+                return;
+            };
+            index
+        };
+        
+        self.preset_ui.select_preset(&mut self.preset_manager, index);
+        if let Some(renderer) = &mut self.renderer {
+            renderer.set_preset_manager(self.preset_manager.clone());
+        }
+    }
+    
     async fn create_window_and_renderer(
         &self, 
         event_loop: &ActiveEventLoop
@@ -270,10 +412,21 @@ impl AppState {
     }
 }
 
+/// File name for a recording started from the keyboard, unique per second
+fn recording_file_name() -> std::path::PathBuf {
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    std::path::PathBuf::from(format!("wcr-viz-recording-{}.wav", seconds))
+}
+
 /// Main graphics system coordinator
 pub struct GraphicsSystem {
     config: GraphicsConfig,
-    audio_receiver: Receiver<AudioEvent>,
+    audio_receiver: RingReceiver<AudioEvent>,
+    control_receiver: Receiver<AudioEvent>,
+    audio_recorder: AudioRecorder,
     preset_manager: PresetManager,
     preset_ui: PresetUI,
 }
@@ -282,7 +435,9 @@ impl GraphicsSystem {
     /// Create a new graphics system
     pub async fn new(
         config: GraphicsConfig,
-        audio_receiver: Receiver<AudioEvent>,
+        audio_receiver: RingReceiver<AudioEvent>,
+        control_receiver: Receiver<AudioEvent>,
+        audio_recorder: AudioRecorder,
         preset_manager: PresetManager,
         preset_ui: PresetUI,
     ) -> Result<Self> {
@@ -291,6 +446,8 @@ impl GraphicsSystem {
         Ok(Self {
             config,
             audio_receiver,
+            control_receiver,
+            audio_recorder,
             preset_manager,
             preset_ui,
         })
@@ -309,7 +466,13 @@ impl GraphicsSystem {
             renderer: None,
             iced_integration: None,
             audio_receiver: self.audio_receiver.clone(),
+            control_receiver: self.control_receiver.clone(),
+            audio_recorder: self.audio_recorder.clone(),
             current_audio_data: None,
+            latency: LatencyCompensator::new(self.config.av_offset_ms),
+            idle: false,
+            last_frame: Instant::now(),
+            resume_preset: None,
             config: self.config.clone(),
             is_running: true,
             preset_manager: self.preset_manager.clone(),
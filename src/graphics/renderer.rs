@@ -4,6 +4,7 @@ use anyhow::Result;
 use std::time::Instant;
 use wgpu::{util::DeviceExt, Buffer, Device, Queue, RenderPipeline, SurfaceConfiguration};
 use crate::audio::AudioData;
+use crate::audio::chroma::KeyMode;
 use crate::ui::UIRenderer;
 use crate::preset::{Preset, PresetManager, renderer::PresetRenderer};
 use swash::{FontRef, zeno};
@@ -945,13 +946,16 @@ fn fs_main(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {
     pub fn update_preset_audio(&mut self, audio_data: &AudioData) -> Result<()> {
         if let Some(ref mut preset_manager) = self.preset_manager {
             if let Some(ref mut preset) = preset_manager.current_preset_mut() {
-                // Update audio variables
-                preset.update_audio_variables(
-                    audio_data.features.bass,
-                    audio_data.features.mid,
-                    audio_data.features.presence,
-                    audio_data.features.volume
-                );
+                // Update audio variables, relative to the song's average as presets expect
+                let levels = &audio_data.features.milkdrop;
+                preset.update_audio_variables(levels.bass, levels.mid, levels.treb, levels.vol);
+                preset.update_attenuated_audio_variables(levels.bass_att, levels.mid_att, levels.treb_att, levels.vol_att);
+                
+                // And harmony, so presets can colour by key
+                let harmony = &audio_data.features.harmony;
+                let minor = if harmony.mode == KeyMode::Minor { 1.0 } else { 0.0 };
+                preset.update_chroma_variables(harmony.chroma, harmony.pitch_class as f32);
+                preset.update_key_variables(harmony.key as f32, minor, harmony.key_confidence, harmony.key_hue());
                 
                 // Execute per-frame equations
                 self.preset_renderer.execute_per_frame_equations(preset)?;
@@ -1,6 +1,8 @@
 use anyhow::Result;
 use clap::Parser;
 use log::{info, error, warn};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::time;
 
@@ -12,7 +14,7 @@ mod ui;
 mod iced_integration;
 
 use audio::AudioSystem;
-use config::Config;
+use config::{Config, PcmFormat, TestSignal};
 use graphics::{GraphicsSystem, GraphicsConfig};
 use preset::PresetManager;
 use ui::PresetUI;
@@ -33,9 +35,61 @@ struct Args {
     #[arg(long)]
     list_devices: bool,
     
+    /// Capture briefly from an input device (default input if no name is given) and report level, clipping and latency
+    #[arg(long, value_name = "NAME")]
+    probe_device: Option<Option<String>>,
+    
     /// Audio device to use (default: system default)
     #[arg(long)]
     device: Option<String>,
+    
+    /// Play an audio file (WAV/FLAC/MP3/OGG) instead of capturing from a device
+    #[arg(long)]
+    input_file: Option<PathBuf>,
+    
+    /// Don't play the input file through the output device
+    #[arg(long)]
+    no_playback: bool,
+    
+    /// Generate a test signal instead of capturing: sine:<hz>, sweep:<from>:<to>:<secs>, white, pink, kick:<bpm> or silence
+    #[arg(long)]
+    signal: Option<TestSignal>,
+    
+    /// Read raw interleaved PCM from stdin: <encoding>:<rate>:<channels>, e.g. f32le:48000:2
+    #[arg(long, value_name = "SPEC")]
+    stdin_pcm: Option<PcmFormat>,
+    
+    /// Read raw PCM from a named pipe instead of stdin (format from --stdin-pcm or the config)
+    #[arg(long, value_name = "PATH")]
+    pcm_fifo: Option<PathBuf>,
+    
+    /// Receive RTP packets with L16 audio on a UDP address, e.g. 0.0.0.0:5004
+    #[arg(long, value_name = "ADDR")]
+    listen_rtp: Option<SocketAddr>,
+    
+    /// Receive raw PCM datagrams on a UDP address (format from --stdin-pcm or the config)
+    #[arg(long, value_name = "ADDR")]
+    listen_udp: Option<SocketAddr>,
+    
+    /// Normalize the input level before analysis (tune with the agc_* config settings)
+    #[arg(long)]
+    agc: bool,
+    
+    /// Record the captured audio to a WAV file (R toggles recording while running)
+    #[arg(long, value_name = "PATH")]
+    record_audio: Option<PathBuf>,
+    
+    /// Record the analyzed audio data to a trace file (JSON lines)
+    #[arg(long, value_name = "PATH")]
+    record_trace: Option<PathBuf>,
+    
+    /// Include the spectra in the trace, about ten times larger
+    #[arg(long, requires = "record_trace")]
+    trace_spectra: bool,
+    
+    /// Replay a recorded trace instead of capturing and analyzing audio
+    #[arg(long, value_name = "PATH")]
+    replay_trace: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -61,18 +115,98 @@ async fn main() -> Result<()> {
         info!("Using specified audio device: {:?}", config.audio.device_name);
     }
     
+    // Override capture mode if an input file is specified
+    if let Some(input_file) = args.input_file {
+        info!("Using audio file input: {}", input_file.display());
+        config.audio.capture_mode = config::AudioCaptureMode::File;
+        config.audio.input_file = Some(input_file);
+    }
+    
+    if args.no_playback {
+        config.audio.file_playback_output = false;
+    }
+    
+    if args.agc {
+        config.audio.agc_enabled = true;
+    }
+    
+    // Override capture mode if a test signal is requested
+    if let Some(signal) = args.signal {
+        info!("Using synthetic test signal: {:?}", signal);
+        config.audio.capture_mode = config::AudioCaptureMode::Synthetic;
+        config.audio.test_signal = signal;
+    }
+    
+    // Override capture mode if raw PCM input is requested
+    if let Some(pcm_format) = args.stdin_pcm {
+        config.audio.capture_mode = config::AudioCaptureMode::Pcm;
+        config.audio.pcm_format = pcm_format;
+    }
+    
+    if let Some(pcm_fifo) = args.pcm_fifo {
+        config.audio.capture_mode = config::AudioCaptureMode::Pcm;
+        config.audio.pcm_path = Some(pcm_fifo);
+    }
+    
+    // Override capture mode if network input is requested
+    if let Some(address) = args.listen_rtp {
+        config.audio.capture_mode = config::AudioCaptureMode::Network;
+        config.audio.network_address = address;
+        config.audio.network_payload = config::NetworkPayload::Rtp;
+    }
+    
+    if let Some(address) = args.listen_udp {
+        config.audio.capture_mode = config::AudioCaptureMode::Network;
+        config.audio.network_address = address;
+        config.audio.network_payload = config::NetworkPayload::Raw;
+    }
+    
+    if matches!(config.audio.capture_mode, config::AudioCaptureMode::Network) {
+        info!("Using network audio input on {} ({:?})", config.audio.network_address, config.audio.network_payload);
+    }
+    
+    if matches!(config.audio.capture_mode, config::AudioCaptureMode::Pcm) {
+        match &config.audio.pcm_path {
+            Some(path) => info!("Using raw PCM input from {} ({:?})", path.display(), config.audio.pcm_format),
+            None => info!("Using raw PCM input from stdin ({:?})", config.audio.pcm_format),
+        }
+    }
+    
     // List audio devices if requested
     if args.list_devices {
         list_audio_devices()?;
         return Ok(());
     }
     
+    if let Some(device_name) = args.probe_device {
+        probe_audio_device(&config.audio, device_name.as_deref())?;
+        return Ok(());
+    }
+    
+    // Check the settings once every override is applied
+    config.validate()?;
+    
     // Initialize audio system
-    let audio_system = Arc::new(AudioSystem::new(&config.audio)?);
+    let audio_source = audio::create_source(&config.audio)?;
+    let audio_system = Arc::new(AudioSystem::new(&config.audio, audio_source)?);
     info!("Audio system initialized");
     
-    // Get audio event receiver
+    if let Some(path) = args.record_audio {
+        audio_system.recorder().start(path)?;
+    }
+    
+    if let Some(path) = args.record_trace {
+        audio_system.trace_recorder().start(path, args.trace_spectra)?;
+    }
+    
+    if let Some(path) = args.replay_trace {
+        info!("Replaying audio trace: {}", path.display());
+        audio_system.replay_trace(audio::TraceReplay::open(&path)?);
+    }
+    
+    // Get audio event receivers
     let audio_receiver = audio_system.event_receiver();
+    let control_receiver = audio_system.control_receiver();
     
     // Initialize preset system
     let preset_manager = PresetManager::new();
@@ -106,11 +240,22 @@ async fn main() -> Result<()> {
         target_fps: config.graphics.target_fps,
         vsync: config.graphics.vsync,
         fullscreen: config.graphics.start_fullscreen,
+        av_offset_ms: config.audio.target_latency_ms,
+        idle_behavior: config.graphics.idle_behavior,
+        idle_fps: config.graphics.idle_fps,
+        idle_preset: config.graphics.idle_preset.clone(),
     };
     
     // Initialize graphics system
     info!("Initializing graphics system with UI integration...");
-    let mut graphics_system = GraphicsSystem::new(graphics_config, audio_receiver, preset_manager, preset_ui).await?;
+    let mut graphics_system = GraphicsSystem::new(
+        graphics_config,
+        audio_receiver,
+        control_receiver,
+        audio_system.recorder(),
+        preset_manager,
+        preset_ui,
+    ).await?;
     info!("Graphics system initialized with UI support");
     
     // Run graphics system (this will block until window is closed)
@@ -140,6 +285,25 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+fn probe_audio_device(config: &config::AudioConfig, device_name: Option<&str>) -> Result<()> {
+    use cpal::traits::DeviceTrait;
+    
+    let input_manager = audio::input::AudioInputManager::new(config)?;
+    let device = input_manager.get_input_device(device_name)?;
+    if let Some(name) = device_name {
+        if device.name().ok().as_deref() != Some(name) {
+            anyhow::bail!("Input device '{}' not found, see --list-devices", name);
+        }
+    }
+    
+    // Long enough to catch intermittent clipping and dropouts
+    info!("Probing input device for 2 seconds...");
+    let probe = input_manager.probe_device(&device, time::Duration::from_secs(2))?;
+    println!("\n{}", probe);
+    
+    Ok(())
+}
+
 fn list_audio_devices() -> Result<()> {
     use cpal::traits::{DeviceTrait, HostTrait};
     
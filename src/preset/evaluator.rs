@@ -103,7 +103,8 @@ impl ExpressionEvaluator {
         // Check if it's a built-in variable
         match s {
             "time" | "frame" | "bass" | "mid" | "treb" | "vol" | "mouse_x" | "mouse_y" |
-            "pixelsx" | "pixelsy" | "bass_att" | "mid_att" | "treb_att" | "vol_att" => {
+            "pixelsx" | "pixelsy" | "bass_att" | "mid_att" | "treb_att" | "vol_att" |
+            "pitch" | "key" | "key_minor" | "key_conf" | "key_hue" => {
                 Ok(Token::Variable(s.to_string()))
             }
             // Check if it's a function
@@ -255,11 +256,21 @@ impl ExpressionEvaluator {
             "mouse_y" => Ok(self.variables.mouse_y),
             "pixelsx" => Ok(1920.0), // Default resolution, should be configurable
             "pixelsy" => Ok(1080.0), // Default resolution, should be configurable
-            "bass_att" => Ok(self.variables.bass), // For now, same as bass
-            "mid_att" => Ok(self.variables.mid),   // For now, same as mid
-            "treb_att" => Ok(self.variables.treb), // For now, same as treb
-            "vol_att" => Ok(self.variables.vol),   // For now, same as vol
+            "bass_att" => Ok(self.variables.bass_att),
+            "mid_att" => Ok(self.variables.mid_att),
+            "treb_att" => Ok(self.variables.treb_att),
+            "vol_att" => Ok(self.variables.vol_att),
+            "pitch" => Ok(self.variables.pitch),
+            "key" => Ok(self.variables.key),
+            "key_minor" => Ok(self.variables.key_minor),
+            "key_conf" => Ok(self.variables.key_conf),
+            "key_hue" => Ok(self.variables.key_hue),
             _ => {
+                // Check if it's a chroma variable
+                if let Some(index) = Self::chroma_index(var_name) {
+                    return Ok(self.variables.chroma[index]);
+                }
+                
                 // Check if it's a q variable
                 if var_name.starts_with('q') && var_name.len() > 1 {
                     if let Ok(index) = var_name[1..].parse::<usize>() {
@@ -289,9 +300,24 @@ impl ExpressionEvaluator {
             "mid" => self.variables.mid = value,
             "treb" => self.variables.treb = value,
             "vol" => self.variables.vol = value,
+            "bass_att" => self.variables.bass_att = value,
+            "mid_att" => self.variables.mid_att = value,
+            "treb_att" => self.variables.treb_att = value,
+            "vol_att" => self.variables.vol_att = value,
+            "pitch" => self.variables.pitch = value,
+            "key" => self.variables.key = value,
+            "key_minor" => self.variables.key_minor = value,
+            "key_conf" => self.variables.key_conf = value,
+            "key_hue" => self.variables.key_hue = value,
             "mouse_x" => self.variables.mouse_x = value,
             "mouse_y" => self.variables.mouse_y = value,
             _ => {
+                // Check if it's a chroma variable
+                if let Some(index) = Self::chroma_index(var_name) {
+                    self.variables.chroma[index] = value;
+                    return Ok(());
+                }
+                
                 // Check if it's a q variable
                 if var_name.starts_with('q') && var_name.len() > 1 {
                     if let Ok(index) = var_name[1..].parse::<usize>() {
@@ -313,6 +339,11 @@ impl ExpressionEvaluator {
         Ok(())
     }
     
+    /// Pitch class of a chroma variable (`chroma0` - `chroma11`)
+    fn chroma_index(var_name: &str) -> Option<usize> {
+        var_name.strip_prefix("chroma")?.parse::<usize>().ok().filter(|&index| index < 12)
+    }
+    
     /// Evaluate a function
     fn evaluate_function(&self, func_name: &str, stack: &mut Vec<f32>) -> Result<f32> {
         match func_name {
@@ -43,6 +43,33 @@ pub struct PresetVariables {
     pub treb: f32,
     pub vol: f32,
     
+    /// Attenuated (smoothed) audio variables
+    #[serde(default)]
+    pub bass_att: f32,
+    #[serde(default)]
+    pub mid_att: f32,
+    #[serde(default)]
+    pub treb_att: f32,
+    #[serde(default)]
+    pub vol_att: f32,
+    
+    /// Harmony variables: chroma per pitch class (`chroma0` = C to `chroma11` = B)
+    /// and the dominant pitch class (`pitch`, 0 - 11)
+    #[serde(default)]
+    pub chroma: [f32; 12],
+    #[serde(default)]
+    pub pitch: f32,
+    
+    /// Estimated key: tonic pitch class, 1.0 if minor, confidence and a hue per key
+    #[serde(default)]
+    pub key: f32,
+    #[serde(default)]
+    pub key_minor: f32,
+    #[serde(default)]
+    pub key_conf: f32,
+    #[serde(default)]
+    pub key_hue: f32,
+    
     /// Time variables
     pub time: f32,
     pub frame: u32,
@@ -63,6 +90,16 @@ impl Default for PresetVariables {
             mid: 0.0,
             treb: 0.0,
             vol: 0.0,
+            bass_att: 0.0,
+            mid_att: 0.0,
+            treb_att: 0.0,
+            vol_att: 0.0,
+            chroma: [0.0; 12],
+            pitch: 0.0,
+            key: 0.0,
+            key_minor: 0.0,
+            key_conf: 0.0,
+            key_hue: 0.0,
             time: 0.0,
             frame: 0,
             mouse_x: 0.0,
@@ -233,6 +270,28 @@ impl Preset {
         self.variables.vol = vol;
     }
     
+    /// Update the attenuated audio variables (`bass_att`, `mid_att`, `treb_att`, `vol_att`)
+    pub fn update_attenuated_audio_variables(&mut self, bass_att: f32, mid_att: f32, treb_att: f32, vol_att: f32) {
+        self.variables.bass_att = bass_att;
+        self.variables.mid_att = mid_att;
+        self.variables.treb_att = treb_att;
+        self.variables.vol_att = vol_att;
+    }
+    
+    /// Update the harmony variables (`chroma0` - `chroma11`, `pitch`)
+    pub fn update_chroma_variables(&mut self, chroma: [f32; 12], pitch: f32) {
+        self.variables.chroma = chroma;
+        self.variables.pitch = pitch;
+    }
+    
+    /// Update the key variables (`key`, `key_minor`, `key_conf`, `key_hue`)
+    pub fn update_key_variables(&mut self, key: f32, key_minor: f32, key_conf: f32, key_hue: f32) {
+        self.variables.key = key;
+        self.variables.key_minor = key_minor;
+        self.variables.key_conf = key_conf;
+        self.variables.key_hue = key_hue;
+    }
+    
     /// Update time variables
     pub fn update_time_variables(&mut self, time: f32, frame: u32) {
         self.variables.time = time;
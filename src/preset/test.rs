@@ -27,6 +27,11 @@ mod tests {
         
         evaluator.evaluate("q2=q1+3").unwrap();
         assert_eq!(evaluator.get_variables().q[1], 8.0);
+        
+        // Chroma variables are indexed by pitch class
+        evaluator.evaluate("chroma9=0.5").unwrap();
+        evaluator.evaluate("q3=chroma9+key_hue").unwrap();
+        assert_eq!(evaluator.get_variables().q[2], 0.5);
     }
 
     #[test]
@@ -68,6 +73,13 @@ ret=ret*0.95
         assert_eq!(preset.variables.mid, 0.3);
         assert_eq!(preset.variables.treb, 0.2);
         assert_eq!(preset.variables.vol, 0.8);
+        preset.update_attenuated_audio_variables(0.4, 0.3, 0.25, 0.7);
+        assert_eq!(preset.variables.bass_att, 0.4);
+        assert_eq!(preset.variables.vol_att, 0.7);
+        preset.update_chroma_variables([0.5; 12], 9.0);
+        preset.update_key_variables(9.0, 1.0, 0.8, 0.25);
+        assert_eq!(preset.variables.chroma[9], 0.5);
+        assert_eq!(preset.variables.key_minor, 1.0);
         
         // Test time variable updates
         preset.update_time_variables(10.5, 100);